@@ -0,0 +1,8 @@
+//! Ring buffers for the MUSI-6106 exercises.
+//!
+//! [`ring_buffer::RingBuffer`] is the single-threaded DSP delay line; [`spsc`] is a
+//! lock-free queue for passing values between threads. Neither allocates after
+//! construction, so both are safe to use from an audio callback.
+
+pub mod ring_buffer;
+pub mod spsc;
@@ -1,7 +1,6 @@
 use std::{fs::File, io::Write};
 
-// mod ring_buffer;
-mod ring_buffer;
+use ase::ring_buffer::{Interpolation, RingBuffer};
 
 fn show_info() {
     eprintln!("MUSI-6106 Assignment Executable");
@@ -13,8 +12,19 @@ fn main() {
 
     // Parse command line arguments
     let args: Vec<String> = std::env::args().collect();
+    if args.get(1).map(String::as_str) == Some("--fuzz-interpolation") {
+        let iterations = args.get(2).map_or(100_000, |n| n.parse().expect("Invalid iteration count"));
+        let seed = args.get(3).map_or(FUZZ_SEED, |n| n.parse().expect("Invalid seed"));
+        let max_errors = fuzz_interpolation(iterations, seed);
+        for (mode, error) in FUZZ_MODES.iter().zip(max_errors) {
+            println!("{:?}: max error {:.2e}", mode, error);
+        }
+        println!("Interpolation fuzzing over {} reads: Passed", iterations);
+        return
+    }
     if args.len() < 3 {
         eprintln!("Usage: {} <input wave filename> <output text filename>", args[0]);
+        eprintln!("       {} --fuzz-interpolation [iterations [seed]]", args[0]);
         return
     }
 
@@ -30,3 +40,84 @@ fn main() {
         write!(out, "{}{}", sample, if i % channels as usize == (channels - 1).into() { "\n" } else { " " }).unwrap();
     }
 }
+
+const FUZZ_MODES: [Interpolation; 3] = [Interpolation::Linear, Interpolation::Hermite, Interpolation::Lagrange];
+const FUZZ_SEED: u64 = 0x9E37_79B9_7F4A_7C15;
+
+// Differential test of the interpolation modes: a band-limited signal streams through
+// one buffer per mode while a random offset trajectory reads it back. All modes must
+// stay close to the true signal and to each other, and agree exactly on whole offsets;
+// a mode that indexes the buffer differently from the others shows up as a large error.
+// Panics on the first read that fails; returns each mode's worst error otherwise.
+fn fuzz_interpolation(iterations: usize, seed: u64) -> [f32; 3] {
+    const LENGTH: usize = 64;
+    // Evaluated in f64 so long runs don't lose the fractional part of the time
+    let signal = |t: f64| {
+        (0.5 * (std::f64::consts::TAU * 0.013 * t + 0.3).sin() + 0.3 * (std::f64::consts::TAU * 0.041 * t + 1.1).sin()) as f32
+    };
+    let modes = FUZZ_MODES;
+    // Worst-case reconstruction error allowed for each mode
+    let tolerances = [0.005, 0.0005, 0.0005];
+    let mut buffers: Vec<RingBuffer<f32>> = modes
+        .iter()
+        .map(|&mode| {
+            let mut rb = RingBuffer::new(LENGTH);
+            rb.set_interpolation(mode);
+            rb
+        })
+        .collect();
+
+    // xorshift64, so runs are reproducible without a rand dependency; it never leaves zero
+    let mut state: u64 = if seed == 0 { FUZZ_SEED } else { seed };
+    let mut random = move || {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        (state >> 40) as f32 / (1u64 << 24) as f32
+    };
+
+    let mut offset = LENGTH as f32 / 2.0;
+    let mut max_errors = [0.0_f32; 3];
+    for step in 0..iterations + LENGTH {
+        buffers.iter_mut().for_each(|rb| rb.push(signal(step as f64)));
+        if step < LENGTH {
+            continue;
+        }
+        let len = buffers[0].len();
+        // Random walk over the offsets where every mode has all four taps, with some whole offsets
+        offset = (offset + 4.0 * (random() - 0.5)).clamp(1.0, (len - 3) as f32);
+        let read_offset = if random() < 0.1 { offset.round() } else { offset };
+        let expected = signal((step + 1 - len) as f64 + read_offset as f64);
+
+        let values: Vec<f32> = buffers.iter().map(|rb| rb.get_frac(read_offset)).collect();
+        for (i, &value) in values.iter().enumerate() {
+            let error = (value - expected).abs();
+            max_errors[i] = max_errors[i].max(error);
+            assert!(
+                error <= tolerances[i],
+                "{:?} read {} at offset {} in step {}, expected {}",
+                modes[i], value, read_offset, step, expected
+            );
+        }
+        if read_offset.fract() == 0.0 {
+            assert!(values.iter().all(|&v| v == values[0]), "Modes disagree at whole offset {}: {:?}", read_offset, values);
+        }
+        let divergence = (values[1] - values[2]).abs();
+        assert!(divergence <= 2.0 * tolerances[1], "Hermite and Lagrange diverge by {} at offset {}", divergence, read_offset);
+    }
+    max_errors
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fuzz_interpolation() {
+        for seed in [FUZZ_SEED, 1, 0xDEAD_BEEF] {
+            let max_errors = fuzz_interpolation(20_000, seed);
+            // The cubic modes must actually beat linear on this signal
+            assert!(max_errors[1] < max_errors[0] && max_errors[2] < max_errors[0], "Fuzz test failed with seed {:#x}: {:?}", seed, max_errors);
+        }
+    }
+}
@@ -3,13 +3,15 @@ pub struct RingBuffer<T> {
     read_index: usize,
     write_index: usize,
     buffer: Vec<T>,
+    interpolation: Interpolation,
 }
 
 impl<T: Copy + Default> RingBuffer<T> {
 /// Initialize the RingBuffer with a given length.
 /// # Example
 /// ```
-/// let rb = RingBuffer::new(10);
+/// use ase::ring_buffer::RingBuffer;
+/// let rb: RingBuffer<f32> = RingBuffer::new(10);
 /// ```
     pub fn new(length: usize) -> Self {
         // Create a new RingBuffer with `length` slots and "default" values.
@@ -19,6 +21,7 @@ impl<T: Copy + Default> RingBuffer<T> {
             read_index: 0,
             write_index: 0,
             buffer: vec![T::default(); length],
+            interpolation: Interpolation::Linear,
         }
     }
 
@@ -85,8 +88,203 @@ impl<T: Copy + Default> RingBuffer<T> {
         }
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.read_index == self.write_index
+    }
+
     pub fn capacity(&self) -> usize {
         // Return the length of the internal buffer.
         self.length_
     }
 }
+
+#[derive(Debug, Clone)]
+pub enum Error {
+    OffsetOutOfRange { offset: f32, len: usize },
+    /// The buffer was created with `RingBuffer::new(0)` and has no slots to read.
+    ZeroCapacity,
+}
+
+/// How `get_frac` reconstructs values between stored samples. The cubic modes use
+/// four taps and keep more of the high end than `Linear`, at roughly twice the cost.
+///
+/// # Example
+/// ```
+/// use ase::ring_buffer::{Interpolation, RingBuffer};
+/// // Worst-case error reading a sine halfway between its samples
+/// let max_error = |interpolation| {
+///     let mut rb: RingBuffer<f32> = RingBuffer::new(64);
+///     rb.set_interpolation(interpolation);
+///     let omega = 0.3;
+///     (0..63).for_each(|n| rb.push((omega * n as f32).sin()));
+///     (1..60)
+///         .map(|n| (rb.get_frac(n as f32 + 0.5) - (omega * (n as f32 + 0.5)).sin()).abs())
+///         .fold(0.0, f32::max)
+/// };
+/// let linear = max_error(Interpolation::Linear);
+/// assert!(max_error(Interpolation::Hermite) < linear / 4.0);
+/// assert!(max_error(Interpolation::Lagrange) < linear / 4.0);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Interpolation {
+    Linear,
+    /// Catmull-Rom cubic Hermite spline.
+    Hermite,
+    /// 3rd-order Lagrange polynomial.
+    Lagrange,
+}
+
+impl Interpolation {
+    // Tap weights for the stored values at -1, 0, 1 and 2 relative to the integer part
+    fn cubic_weights(self, frac: f32) -> [f32; 4] {
+        let t = frac;
+        match self {
+            Interpolation::Hermite => [
+                ((-0.5 * t + 1.0) * t - 0.5) * t,
+                (1.5 * t - 2.5) * t * t + 1.0,
+                ((-1.5 * t + 2.0) * t + 0.5) * t,
+                (0.5 * t - 0.5) * t * t,
+            ],
+            Interpolation::Lagrange => [
+                -t * (t - 1.0) * (t - 2.0) / 6.0,
+                (t + 1.0) * (t - 1.0) * (t - 2.0) / 2.0,
+                -(t + 1.0) * t * (t - 2.0) / 2.0,
+                (t + 1.0) * t * (t - 1.0) / 6.0,
+            ],
+            Interpolation::Linear => [0.0, 1.0 - t, t, 0.0],
+        }
+    }
+}
+
+/// Element types that `get_frac` can interpolate between. The buffer itself only needs
+/// `Copy + Default`, so it can also hold events or other plain structs.
+pub trait Interpolate: Copy + Default {
+    /// Value `frac` (in `0.0..1.0`) of the way from `a` to `b`.
+    fn lerp(a: Self, b: Self, frac: f32) -> Self;
+
+    /// Sum of `taps` scaled by `weights`, used by the cubic interpolation modes.
+    fn weighted_sum(taps: [Self; 4], weights: [f32; 4]) -> Self;
+}
+
+impl Interpolate for f32 {
+    fn lerp(a: Self, b: Self, frac: f32) -> Self {
+        a + frac * (b - a)
+    }
+
+    fn weighted_sum(taps: [Self; 4], weights: [f32; 4]) -> Self {
+        taps.iter().zip(weights).map(|(tap, weight)| tap * weight).sum()
+    }
+}
+
+impl Interpolate for f64 {
+    fn lerp(a: Self, b: Self, frac: f32) -> Self {
+        a + frac as f64 * (b - a)
+    }
+
+    fn weighted_sum(taps: [Self; 4], weights: [f32; 4]) -> Self {
+        taps.iter().zip(weights).map(|(tap, weight)| tap * weight as f64).sum()
+    }
+}
+
+impl<T: Interpolate> RingBuffer<T> {
+    /// Read between stored values using the buffer's interpolation mode (linear unless
+    /// changed with `set_interpolation`). `offset` counts from the
+    /// read index like `get`, but may be fractional. Both interpolation taps must hold
+    /// values that have been written, so the valid range is `0.0..=len() - 1`; anything
+    /// outside it would read stale or not-yet-written slots.
+    ///
+    /// Out-of-range offsets trip a debug assertion; use `try_get_frac` to check at runtime.
+    /// A buffer with zero capacity reads as `T::default()` in release builds.
+    pub fn get_frac(&self, offset: f32) -> T {
+        debug_assert!(
+            self.offset_in_range(offset),
+            "get_frac offset {} outside of the {} values in the buffer",
+            offset,
+            self.len()
+        );
+        if self.length_ == 0 {
+            return T::default();
+        }
+        let index = offset.floor();
+        let frac = offset - index;
+        let current = self.get(index as usize);
+        if frac == 0.0 {
+            return current;
+        }
+        let next = self.get(index as usize + 1);
+        if self.interpolation == Interpolation::Linear {
+            return T::lerp(current, next, frac);
+        }
+        // The outer taps are held at the first/last stored value near the ends
+        let index = index as usize;
+        let before = self.get(index.saturating_sub(1));
+        let after = self.get((index + 2).min(self.len().saturating_sub(1)));
+        T::weighted_sum([before, current, next, after], self.interpolation.cubic_weights(frac))
+    }
+
+    /// Like `get_frac`, but returns an error instead of reading outside the stored values.
+    pub fn try_get_frac(&self, offset: f32) -> Result<T, Error> {
+        if self.length_ == 0 {
+            return Err(Error::ZeroCapacity);
+        }
+        if !self.offset_in_range(offset) {
+            return Err(Error::OffsetOutOfRange { offset, len: self.len() });
+        }
+        Ok(self.get_frac(offset))
+    }
+
+    pub fn interpolation(&self) -> Interpolation {
+        self.interpolation
+    }
+
+    pub fn set_interpolation(&mut self, interpolation: Interpolation) {
+        self.interpolation = interpolation;
+    }
+
+    fn offset_in_range(&self, offset: f32) -> bool {
+        !self.is_empty() && offset >= 0.0 && offset <= (self.len() - 1) as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filled(values: &[f32]) -> RingBuffer<f32> {
+        let mut rb = RingBuffer::new(8);
+        values.iter().for_each(|&x| rb.push(x));
+        rb
+    }
+
+    #[test]
+    fn test_try_get_frac_in_range() {
+        let rb = filled(&[1.0, 2.0, 4.0]);
+        assert_eq!(rb.try_get_frac(0.0).unwrap(), 1.0);
+        assert_eq!(rb.try_get_frac(1.5).unwrap(), 3.0);
+        assert_eq!(rb.try_get_frac(2.0).unwrap(), 4.0);
+    }
+
+    #[test]
+    fn test_try_get_frac_rejects_out_of_range_offsets() {
+        let rb = filled(&[1.0, 2.0, 4.0]);
+        for offset in [-0.5, -f32::EPSILON, 2.01, 3.0, f32::INFINITY, f32::NAN] {
+            assert!(
+                matches!(rb.try_get_frac(offset), Err(Error::OffsetOutOfRange { len: 3, .. })),
+                "Offset test failed: {} wasn't rejected.",
+                offset
+            );
+        }
+        let empty: RingBuffer<f32> = RingBuffer::new(8);
+        assert!(matches!(empty.try_get_frac(0.0), Err(Error::OffsetOutOfRange { len: 0, .. })));
+    }
+
+    #[test]
+    fn test_zero_capacity() {
+        let mut rb: RingBuffer<f32> = RingBuffer::new(0);
+        assert!(matches!(rb.try_get_frac(0.0), Err(Error::ZeroCapacity)));
+        rb.set_interpolation(Interpolation::Hermite);
+        assert!(matches!(rb.try_get_frac(0.5), Err(Error::ZeroCapacity)));
+        assert_eq!(rb.len(), 0);
+        assert!(rb.is_empty());
+    }
+}
@@ -0,0 +1,157 @@
+use std::cell::UnsafeCell;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+// Single-producer single-consumer queue for passing values between threads
+// (e.g. audio thread <-> UI thread) without locks. The DSP `RingBuffer` is
+// not thread-safe; this one is, but only for exactly one writer and one reader.
+struct Shared<T> {
+    buffer: Box<[UnsafeCell<T>]>,
+    // Next slot to read, only advanced by the consumer
+    read_index: AtomicUsize,
+    // Next slot to write, only advanced by the producer
+    write_index: AtomicUsize,
+}
+
+// The producer only writes slots the consumer has released and vice versa,
+// with the index stores publishing the slot contents.
+unsafe impl<T: Send> Sync for Shared<T> {}
+
+pub struct Producer<T> {
+    shared: Arc<Shared<T>>,
+}
+
+pub struct Consumer<T> {
+    shared: Arc<Shared<T>>,
+}
+
+/// Create a queue holding up to `capacity` values and return its two ends.
+/// Neither `push` nor `pop` allocates or blocks.
+/// # Example
+/// ```
+/// use ase::spsc;
+/// let (mut tx, mut rx) = spsc::channel::<f32>(4);
+/// tx.push(1.0).unwrap();
+/// assert_eq!(rx.pop(), Some(1.0));
+/// ```
+pub fn channel<T: Copy + Default>(capacity: usize) -> (Producer<T>, Consumer<T>) {
+    // One slot stays empty so a full queue can be told apart from an empty one.
+    let buffer = (0..capacity + 1).map(|_| UnsafeCell::new(T::default())).collect();
+    let shared = Arc::new(Shared {
+        buffer,
+        read_index: AtomicUsize::new(0),
+        write_index: AtomicUsize::new(0),
+    });
+    (Producer { shared: shared.clone() }, Consumer { shared })
+}
+
+impl<T> Shared<T> {
+    fn slots(&self) -> usize {
+        self.buffer.len()
+    }
+
+    fn len(&self) -> usize {
+        let read = self.read_index.load(Ordering::Acquire);
+        let write = self.write_index.load(Ordering::Acquire);
+        (write + self.slots() - read) % self.slots()
+    }
+}
+
+impl<T: Copy> Producer<T> {
+    /// Append a value, or hand it back if the queue is full.
+    pub fn push(&mut self, value: T) -> Result<(), T> {
+        let write = self.shared.write_index.load(Ordering::Relaxed);
+        let next = (write + 1) % self.shared.slots();
+        if next == self.shared.read_index.load(Ordering::Acquire) {
+            return Err(value);
+        }
+        // SAFETY: the consumer never reads the slot at write_index until we publish it below.
+        unsafe { *self.shared.buffer[write].get() = value };
+        self.shared.write_index.store(next, Ordering::Release);
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.shared.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.shared.len() == 0
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.shared.slots() - 1
+    }
+}
+
+impl<T: Copy> Consumer<T> {
+    /// Take the oldest value, or `None` if the queue is empty.
+    pub fn pop(&mut self) -> Option<T> {
+        let read = self.shared.read_index.load(Ordering::Relaxed);
+        if read == self.shared.write_index.load(Ordering::Acquire) {
+            return None;
+        }
+        // SAFETY: the producer published this slot and won't write it again until we release it below.
+        let value = unsafe { *self.shared.buffer[read].get() };
+        self.shared.read_index.store((read + 1) % self.shared.slots(), Ordering::Release);
+        Some(value)
+    }
+
+    pub fn len(&self) -> usize {
+        self.shared.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.shared.len() == 0
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.shared.slots() - 1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_values_cross_threads_in_order() {
+        const NUM_VALUES: u64 = 200_000;
+        // A small queue, so the producer keeps running into a full queue and the consumer into an empty one
+        let (mut tx, mut rx) = channel::<u64>(3);
+        let producer = std::thread::spawn(move || {
+            for value in 0..NUM_VALUES {
+                while tx.push(value).is_err() {
+                    std::thread::yield_now();
+                }
+            }
+        });
+
+        let mut expected = 0;
+        while expected < NUM_VALUES {
+            match rx.pop() {
+                Some(value) => {
+                    assert_eq!(value, expected, "SPSC test failed: values arrived out of order.");
+                    expected += 1;
+                }
+                None => std::thread::yield_now(),
+            }
+        }
+        producer.join().unwrap();
+        assert_eq!(rx.pop(), None, "SPSC test failed: more values arrived than were pushed.");
+    }
+
+    #[test]
+    fn test_full_queue_hands_value_back() {
+        let (mut tx, mut rx) = channel::<f32>(2);
+        assert_eq!(tx.capacity(), 2);
+        assert_eq!(tx.push(1.0), Ok(()));
+        assert_eq!(tx.push(2.0), Ok(()));
+        assert_eq!(tx.push(3.0), Err(3.0));
+        assert_eq!(rx.len(), 2);
+        assert_eq!(rx.pop(), Some(1.0));
+        assert_eq!(tx.push(3.0), Ok(()));
+        assert_eq!([rx.pop(), rx.pop(), rx.pop()], [Some(2.0), Some(3.0), None]);
+        assert!(tx.is_empty());
+    }
+}
@@ -0,0 +1,142 @@
+use crate::effect::AudioEffect;
+use crate::error::Error;
+use crate::meter::BlockStats;
+
+// Renders of internally generated test signals, so an effect can be checked without
+// an input file. The measured latency is where the output lines up best with the
+// input, which hosts can compare against the effect's reported latency_samples().
+
+/// Test signal for `analyze`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TestSignal {
+    /// A single full-scale sample at the start, then silence.
+    Impulse,
+    /// Exponential sine sweep from 20 Hz to just below Nyquist at half scale.
+    Sweep,
+    /// Constant 0.5.
+    Dc,
+}
+
+impl TestSignal {
+    /// Parse an `--analyze` value: `impulse`, `sweep` or `dc`.
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "impulse" => Some(TestSignal::Impulse),
+            "sweep" => Some(TestSignal::Sweep),
+            "dc" => Some(TestSignal::Dc),
+            _ => None,
+        }
+    }
+
+    pub fn generate(self, sample_rate_hz: f32, num_frames: usize) -> Vec<f32> {
+        match self {
+            TestSignal::Impulse => (0..num_frames).map(|n| if n == 0 { 1.0 } else { 0.0 }).collect(),
+            TestSignal::Dc => vec![0.5; num_frames],
+            TestSignal::Sweep => {
+                let start_hz = 20.0_f64;
+                let end_hz = 0.45 * sample_rate_hz as f64;
+                let duration_secs = num_frames as f64 / sample_rate_hz as f64;
+                let rate = (end_hz / start_hz).ln() / duration_secs;
+                (0..num_frames)
+                    .map(|n| {
+                        let t = n as f64 / sample_rate_hz as f64;
+                        let phase = std::f64::consts::TAU * start_hz * ((rate * t).exp() - 1.0) / rate;
+                        (0.5 * phase.sin()) as f32
+                    })
+                    .collect()
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnalysisReport {
+    pub peak: f32,
+    /// Lag (in samples) at which the output best matches the input; `None` for DC,
+    /// which matches equally well at every lag.
+    pub measured_latency: Option<usize>,
+    /// What the effect itself reports through `latency_samples()`.
+    pub reported_latency: usize,
+}
+
+/// Run `signal` through `effect` (every channel gets the same signal) in blocks of
+/// `block_size`, returning the output channels and the measurements. Latencies are
+/// searched up to one block. A `block_size` of 0 is an error.
+pub fn analyze(
+    effect: &mut dyn AudioEffect,
+    signal: TestSignal,
+    sample_rate_hz: f32,
+    num_frames: usize,
+    block_size: usize,
+) -> Result<(Vec<Vec<f32>>, AnalysisReport), Error> {
+    if block_size == 0 {
+        return Err(Error::InvalidValue { param: "block_size", value: 0.0 });
+    }
+    let input = signal.generate(sample_rate_hz, num_frames);
+    let num_channels = effect.num_channels();
+    let mut output = vec![vec![0.0; num_frames]; num_channels];
+    let mut input_block = vec![0.0; block_size];
+    let mut output_blocks = vec![vec![0.0; block_size]; num_channels];
+    for start in (0..num_frames).step_by(block_size) {
+        // Zero-pad the last block so every call gets a full block
+        let len = (num_frames - start).min(block_size);
+        input_block[..len].copy_from_slice(&input[start..start + len]);
+        input_block[len..].fill(0.0);
+        effect.process(
+            &vec![&input_block[..]; num_channels],
+            &mut output_blocks.iter_mut().map(|x| &mut x[..]).collect::<Vec<_>>(),
+        );
+        for (channel, block) in output.iter_mut().zip(&output_blocks) {
+            channel[start..start + len].copy_from_slice(&block[..len]);
+        }
+    }
+
+    let peak = output.iter().map(|channel| BlockStats::measure(channel).peak).fold(0.0, f32::max);
+    let measured_latency = match signal {
+        TestSignal::Dc => None,
+        _ => output.first().map(|channel| best_lag(&input, channel, block_size.min(num_frames))),
+    };
+    let report = AnalysisReport { peak, measured_latency, reported_latency: effect.latency_samples() };
+    Ok((output, report))
+}
+
+// Lag below `max_lag` with the largest cross-correlation between `input` and `output`
+fn best_lag(input: &[f32], output: &[f32], max_lag: usize) -> usize {
+    let correlations: Vec<f64> = (0..max_lag.min(output.len()))
+        .map(|lag| input.iter().zip(&output[lag..]).map(|(&x, &y)| x as f64 * y as f64).sum())
+        .collect();
+    (0..correlations.len()).max_by(|&a, &b| correlations[a].total_cmp(&correlations[b])).unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::comb_filter::{CombFilter, FilterType};
+
+    #[test]
+    fn test_analysis_measures_peak_and_latency() {
+        // With the delayed path louder than the dry one, the best match is at the delay
+        let mut filter = CombFilter::new(FilterType::FIR, 0.1, 1000.0, 1, 1.5, 0.01).expect("Failed to create CombFilter");
+        let (output, report) = analyze(&mut filter, TestSignal::Impulse, 1000.0, 100, 32).expect("Failed to analyze");
+        assert_eq!(output[0].len(), 100);
+        assert_eq!(report.peak, 1.5);
+        assert_eq!(report.measured_latency, Some(10));
+        assert_eq!(report.reported_latency, 0);
+        let mut filter = CombFilter::new(FilterType::FIR, 0.1, 1000.0, 1, 0.5, 0.01).expect("Failed to create CombFilter");
+        let (_, report) = analyze(&mut filter, TestSignal::Sweep, 1000.0, 1000, 32).expect("Failed to analyze");
+        assert_eq!(report.measured_latency, Some(0));
+        let (_, report) = analyze(&mut filter, TestSignal::Dc, 1000.0, 1000, 32).expect("Failed to analyze");
+        assert_eq!(report.measured_latency, None);
+        assert!((report.peak - 0.75).abs() < 1e-6, "Analysis test failed: DC peak {} is not 0.75.", report.peak);
+    }
+
+    #[test]
+    fn test_analysis_with_long_delay_and_zero_block_size() {
+        // 100 samples of delay analyzed in 32-sample blocks
+        let mut filter = CombFilter::new(FilterType::FIR, 0.2, 1000.0, 1, 0.5, 0.1).expect("Failed to create CombFilter");
+        let (output, report) = analyze(&mut filter, TestSignal::Impulse, 1000.0, 200, 32).expect("Failed to analyze");
+        assert_eq!((output[0][0], output[0][100]), (1.0, 0.5));
+        assert_eq!(report.peak, 1.0);
+        assert!(matches!(analyze(&mut filter, TestSignal::Impulse, 1000.0, 200, 0), Err(Error::InvalidValue { param: "block_size", .. })));
+    }
+}
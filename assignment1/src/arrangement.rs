@@ -0,0 +1,273 @@
+use std::path::{Path, PathBuf};
+
+use hound::{SampleFormat, WavReader, WavSpec, WavWriter};
+
+use crate::effect::AudioEffect;
+use crate::envelope::Envelope;
+use crate::error::Error;
+use crate::memory::MemoryUsage;
+use crate::render::{self, BlockWriter, FilterSettings, RenderReport};
+use crate::sample_format::{self, OutputFormat};
+use crate::units;
+
+// Offline multi-file renderer: input files are placed on tracks at time offsets,
+// each track runs through its own filter chain, and the sum of all tracks runs
+// through a master chain into one output file. Region audio is read into memory
+// up front, with each region's fades and gain envelope applied as it is loaded;
+// the processing itself is block-based like `render::run`.
+
+/// One input file placed on a track.
+#[derive(Debug, Clone)]
+pub struct Region {
+    pub input_path: PathBuf,
+    /// Where the file starts on the timeline.
+    pub start_secs: f32,
+    /// Linear fade from silence over the start of the region.
+    pub fade_in_secs: f32,
+    /// Linear fade to silence over the end of the region.
+    pub fade_out_secs: f32,
+    /// Gain over time, with time measured from the start of the region.
+    pub gain: Envelope,
+}
+
+impl Region {
+    /// Region at `start_secs` with unity gain and no fades.
+    pub fn new(input_path: impl Into<PathBuf>, start_secs: f32) -> Self {
+        Region {
+            input_path: input_path.into(),
+            start_secs,
+            fade_in_secs: 0.0,
+            fade_out_secs: 0.0,
+            gain: Envelope::constant(1.0),
+        }
+    }
+
+    // Combined fade and envelope gain at `frame` of a region `num_frames` long
+    fn gain_at(&self, frame: usize, num_frames: usize, sample_rate_hz: f32) -> f32 {
+        let fade = |frames_from_edge: usize, fade_secs: f32| {
+            let fade_frames = units::secs_to_frac_samples(fade_secs, sample_rate_hz);
+            if fade_frames > 0.0 {
+                (frames_from_edge as f32 / fade_frames).min(1.0)
+            } else {
+                1.0
+            }
+        };
+        let fade_in = fade(frame, self.fade_in_secs);
+        let fade_out = fade(num_frames - 1 - frame, self.fade_out_secs);
+        fade_in * fade_out * self.gain.value_at(units::samples_to_secs(frame, sample_rate_hz))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Track {
+    pub regions: Vec<Region>,
+    /// Filters applied to this track only, first to last.
+    pub filters: Vec<FilterSettings>,
+}
+
+/// A set of tracks mixed to one output. Every region file must have this sample
+/// rate and channel count.
+#[derive(Debug, Clone)]
+pub struct Arrangement {
+    pub sample_rate: u32,
+    pub num_channels: usize,
+    pub tracks: Vec<Track>,
+    /// Filters applied to the sum of all tracks.
+    pub master_filters: Vec<FilterSettings>,
+    pub block_size: usize,
+    pub output_format: OutputFormat,
+    /// Fail before loading any audio if the render would need more bytes than this.
+    pub max_memory_bytes: Option<usize>,
+}
+
+// Region audio, one Vec per channel, and its first frame on the timeline
+struct LoadedRegion {
+    start_frame: usize,
+    channels: Vec<Vec<f32>>,
+}
+
+impl LoadedRegion {
+    fn end_frame(&self) -> usize {
+        self.start_frame + self.channels.first().map_or(0, Vec::len)
+    }
+}
+
+/// Render `arrangement` to `output_path`. The output ends with the last region; effect
+/// tails after that are cut off, as in `render::run`.
+pub fn render(arrangement: &Arrangement, output_path: &Path) -> Result<RenderReport, Error> {
+    let channels = arrangement.num_channels;
+    let block_size = arrangement.block_size;
+    let sample_rate_hz = arrangement.sample_rate as f32;
+
+    let memory = estimate_memory(arrangement)?;
+    memory.check(arrangement.max_memory_bytes)?;
+
+    let mut tracks = Vec::with_capacity(arrangement.tracks.len());
+    for track in &arrangement.tracks {
+        let regions = track
+            .regions
+            .iter()
+            .map(|region| load_region(region, arrangement))
+            .collect::<Result<Vec<_>, Error>>()?;
+        let chain = render::build_chain(&render::at_sample_rate(&track.filters, sample_rate_hz), channels, block_size, 0.0, false)?;
+        tracks.push((regions, chain));
+    }
+    let mut master = render::build_chain(&render::at_sample_rate(&arrangement.master_filters, sample_rate_hz), channels, block_size, 0.0, false)?;
+    let num_frames = tracks
+        .iter()
+        .flat_map(|(regions, _)| regions.iter().map(LoadedRegion::end_frame))
+        .max()
+        .unwrap_or(0);
+
+    let spec = arrangement.output_format.apply(WavSpec {
+        channels: channels as u16,
+        sample_rate: arrangement.sample_rate,
+        bits_per_sample: 32,
+        sample_format: SampleFormat::Float,
+    });
+    let mut output = BlockWriter::new(WavWriter::create(output_path, spec)?, channels);
+
+    let mut track_in: Vec<Vec<f32>> = vec![vec![0.0; block_size]; channels];
+    let mut track_out: Vec<Vec<f32>> = vec![vec![0.0; block_size]; channels];
+    let mut mix: Vec<Vec<f32>> = vec![vec![0.0; block_size]; channels];
+    let mut master_out: Vec<Vec<f32>> = vec![vec![0.0; block_size]; channels];
+
+    let mut block_start = 0;
+    while block_start < num_frames {
+        let block_end = (block_start + block_size).min(num_frames);
+        mix.iter_mut().for_each(|channel| channel.fill(0.0));
+
+        for (regions, chain) in &mut tracks {
+            // Copy the part of each region that overlaps this block
+            track_in.iter_mut().for_each(|channel| channel.fill(0.0));
+            for region in regions.iter() {
+                let from = block_start.max(region.start_frame);
+                let to = block_end.min(region.end_frame());
+                if from >= to {
+                    continue;
+                }
+                for (input, source) in track_in.iter_mut().zip(&region.channels) {
+                    input[from - block_start..to - block_start]
+                        .copy_from_slice(&source[from - region.start_frame..to - region.start_frame]);
+                }
+            }
+            process(chain, &track_in, &mut track_out);
+            for (mix_channel, track_channel) in mix.iter_mut().zip(&track_out) {
+                mix_channel.iter_mut().zip(track_channel).for_each(|(m, t)| *m += t);
+            }
+        }
+
+        process(&mut master, &mix, &mut master_out);
+        output.write_block(&master_out, block_end - block_start, render::metered_stats(&master, block_end - block_start, block_size))?;
+        block_start = block_end;
+    }
+
+    let mut report = output.finish()?;
+    report.memory = memory;
+    Ok(report)
+}
+
+/// What `render` would allocate for `arrangement`. Only reads the region file headers.
+pub fn estimate_memory(arrangement: &Arrangement) -> Result<MemoryUsage, Error> {
+    let channels = arrangement.num_channels;
+    let sample_rate_hz = arrangement.sample_rate as f32;
+    let mut memory = MemoryUsage::default();
+    for track in &arrangement.tracks {
+        for region in &track.regions {
+            memory.add_audio(channels, WavReader::open(&region.input_path)?.duration() as usize);
+        }
+        memory.add_filters(&render::at_sample_rate(&track.filters, sample_rate_hz), channels);
+    }
+    memory.add_filters(&render::at_sample_rate(&arrangement.master_filters, sample_rate_hz), channels);
+    // Two scratch buffers per chain (tracks and master) and the four mixing buffers
+    memory.add_buffers(2 * (arrangement.tracks.len() + 1) + 4, channels, arrangement.block_size);
+    Ok(memory)
+}
+
+fn process(effect: &mut dyn AudioEffect, input: &[Vec<f32>], output: &mut [Vec<f32>]) {
+    let input_slices: Vec<&[f32]> = input.iter().map(|v| v.as_slice()).collect();
+    let mut output_slices: Vec<&mut [f32]> = output.iter_mut().map(|v| v.as_mut_slice()).collect();
+    effect.process(&input_slices, &mut output_slices);
+}
+
+fn load_region(region: &Region, arrangement: &Arrangement) -> Result<LoadedRegion, Error> {
+    let mut reader = WavReader::open(&region.input_path)?;
+    let spec = reader.spec();
+    if spec.sample_rate != arrangement.sample_rate || spec.channels as usize != arrangement.num_channels {
+        return Err(Error::IncompatibleInput {
+            path: region.input_path.clone(),
+            sample_rate: spec.sample_rate,
+            channels: spec.channels,
+        });
+    }
+    let mut channels = vec![Vec::with_capacity(reader.duration() as usize); arrangement.num_channels];
+    for (i, sample) in sample_format::samples_f32(&mut reader).enumerate() {
+        channels[i % arrangement.num_channels].push(sample?);
+    }
+    // Fades and gain are applied once here, sample by sample, rather than per block
+    let num_frames = channels.first().map_or(0, Vec::len);
+    let sample_rate_hz = arrangement.sample_rate as f32;
+    for frame in 0..num_frames {
+        let gain = region.gain_at(frame, num_frames, sample_rate_hz);
+        channels.iter_mut().for_each(|channel| channel[frame] *= gain);
+    }
+    Ok(LoadedRegion {
+        start_frame: units::secs_to_samples(region.start_secs, arrangement.sample_rate as f32),
+        channels,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_wav::{read_wav, temp_path, write_wav};
+
+    #[test]
+    fn test_arrangement_mixes_offset_regions() {
+        // A 10-sample impulse file placed at 0 and at 5 ms on two tracks (1 kHz, so 5 samples apart)
+        let impulse_path = write_wav("impulse.wav", 1, 1000, (0..10).map(|n| if n == 0 { 0.5 } else { 0.0 }));
+        let output_path = temp_path("arrangement.wav");
+
+        let track = |start_secs: f32| Track { regions: vec![Region::new(&impulse_path, start_secs)], filters: Vec::new() };
+        let arrangement = Arrangement {
+            sample_rate: 1000,
+            num_channels: 1,
+            tracks: vec![track(0.0), track(0.005)],
+            master_filters: Vec::new(),
+            block_size: 4,
+            output_format: OutputFormat::Float32,
+            max_memory_bytes: None,
+        };
+        let report = render(&arrangement, &output_path).expect("Failed to render arrangement");
+        assert_eq!(report.num_frames, 15);
+        let output = read_wav(&output_path);
+        let expected: Vec<f32> = (0..15).map(|n| if n == 0 || n == 5 { 0.5 } else { 0.0 }).collect();
+        assert_eq!(output, expected, "Arrangement test failed: regions not mixed at their offsets.");
+    }
+
+    #[test]
+    fn test_arrangement_region_fades_and_gain() {
+        // 20 samples of DC at 1 kHz with a 4-sample fade in, a 5-sample fade out and -6 dB-ish gain from 10 ms on
+        let dc_path = write_wav("dc.wav", 1, 1000, [1.0; 20]);
+        let output_path = temp_path("fades.wav");
+
+        let mut region = Region::new(&dc_path, 0.0);
+        region.fade_in_secs = 0.004;
+        region.fade_out_secs = 0.005;
+        region.gain = Envelope::new(vec![(0.0, 1.0), (0.01, 1.0), (0.01, 0.5)]).expect("Failed to create envelope");
+        let arrangement = Arrangement {
+            sample_rate: 1000,
+            num_channels: 1,
+            tracks: vec![Track { regions: vec![region], filters: Vec::new() }],
+            master_filters: Vec::new(),
+            block_size: 8,
+            output_format: OutputFormat::Float32,
+            max_memory_bytes: None,
+        };
+        render(&arrangement, &output_path).expect("Failed to render arrangement");
+        let output = read_wav(&output_path);
+        assert_eq!(&output[..5], &[0.0, 0.25, 0.5, 0.75, 1.0]);
+        assert_eq!(&output[9..11], &[1.0, 0.5]);
+        assert_eq!(&output[15..], &[0.4, 0.3, 0.2, 0.1, 0.0]);
+    }
+}
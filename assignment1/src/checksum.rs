@@ -0,0 +1,66 @@
+// Canonical checksum of rendered audio, for checking that a render reproduces
+// bit-exactly on another machine. Only the sample values are hashed (interleaved,
+// as normalized f32 bit patterns), never the file header, so the result does not
+// depend on how the container was written.
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
+/// 64-bit FNV-1a over the little-endian bytes of each sample.
+pub struct SampleHasher {
+    state: u64,
+}
+
+impl SampleHasher {
+    pub fn new() -> Self {
+        SampleHasher { state: FNV_OFFSET_BASIS }
+    }
+
+    pub fn update(&mut self, sample: f32) {
+        for byte in sample.to_bits().to_le_bytes() {
+            self.state ^= byte as u64;
+            self.state = self.state.wrapping_mul(FNV_PRIME);
+        }
+    }
+
+    pub fn finish(&self) -> u64 {
+        self.state
+    }
+}
+
+impl Default for SampleHasher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub fn to_hex(hash: u64) -> String {
+    format!("{:016x}", hash)
+}
+
+pub fn from_hex(hex: &str) -> Option<u64> {
+    u64::from_str_radix(hex.trim_start_matches("0x"), 16).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sample_hasher_matches_fnv1a_vectors() {
+        // Published 64-bit FNV-1a values for "" and "foob"; one sample holds four bytes
+        assert_eq!(SampleHasher::new().finish(), 0xcbf29ce484222325);
+        let mut hasher = SampleHasher::new();
+        hasher.update(f32::from_bits(u32::from_le_bytes(*b"foob")));
+        assert_eq!(hasher.finish(), 0xdd120e790c2512af, "FNV-1a test failed: hash of \"foob\" differs.");
+    }
+
+    #[test]
+    fn test_hex_round_trip() {
+        assert_eq!(to_hex(0xdd120e790c2512af), "dd120e790c2512af");
+        assert_eq!(to_hex(0x1f), "000000000000001f");
+        assert_eq!(from_hex("dd120e790c2512af"), Some(0xdd120e790c2512af));
+        assert_eq!(from_hex("0x1f"), Some(0x1f));
+        assert_eq!(from_hex("not hex"), None);
+    }
+}
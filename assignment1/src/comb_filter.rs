@@ -1,3 +1,15 @@
+use std::fmt;
+use std::ops::{Range, RangeInclusive};
+
+use crate::effect::AudioEffect;
+use crate::meter::{BlockStats, StatsAccumulator};
+use crate::smoother::{Smoother, SmoothingType};
+use crate::state::{self, StateReader, StateWriter};
+use crate::units::secs_to_frac_samples;
+
+/// Largest gain an IIR filter accepts: the feedback loop only decays for gains below 1.
+pub const MAX_IIR_GAIN: f32 = 1.0 - f32::EPSILON / 2.0;
+
 pub struct CombFilter {
     // TODO: your code here
     max_delay_secs: f32,
@@ -5,9 +17,34 @@ pub struct CombFilter {
     num_channels: usize,
     filter_type: FilterType,
     buffer: Vec<Vec<f32>>,
-    gain: f32,
-    delay_samples: usize,
+    // Samples written to each delay line since the last reset, up to its length
+    fill: usize,
+    // Per-channel targets; all equal unless the link mode is DualMono
+    gain: Vec<f32>,
+    gain_smoothers: Vec<Smoother>,
+    // Target delay, kept fractional; process() interpolates between neighbouring samples.
+    delay_samples: Vec<f32>,
+    delay_smoothers: Vec<Smoother>,
+    link_mode: LinkMode,
     writer_idx: Vec<usize>,
+    // Per-channel stats of the last block, only collected while metering is enabled
+    stats: Option<Vec<BlockStats>>,
+    callbacks: Callbacks,
+    // Check every output block for NaN/Inf and recover from it
+    strict: bool,
+    num_recoveries: usize,
+    // Clamp out-of-range set_param values instead of returning an error
+    permissive: bool,
+    num_clamped: usize,
+}
+
+// Optional hooks for embedding applications (UIs, logging). Each is called
+// synchronously from the method that triggers it.
+#[derive(Default)]
+struct Callbacks {
+    on_block_processed: Option<Box<dyn FnMut(usize) + Send>>,
+    on_param_changed: Option<Box<dyn FnMut(FilterParam, f32) + Send>>,
+    on_clip_detected: Option<Box<dyn FnMut(usize, usize) + Send>>,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -16,7 +53,16 @@ pub enum FilterType {
     IIR,
 }
 
-#[derive(Debug, Clone, Copy)]
+/// Whether the channels share one set of parameters or each has its own.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LinkMode {
+    /// Every parameter change applies to all channels (the default).
+    Linked,
+    /// Each channel keeps its own gain and delay, set with `set_channel_param`.
+    DualMono,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum FilterParam {
     Gain,
     Delay,
@@ -27,7 +73,87 @@ pub enum Error {
     InvalidValue { param: FilterParam, value: f32 }
 }
 
+/// One sample of `CombFilter::process_traced`. The delayed value is read `delay_samples`
+/// back, mixing the samples at its floor and floor + 1 with `weights`; a delay of zero
+/// reads the current input.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SampleTrace {
+    pub input: f32,
+    pub delay_samples: f32,
+    pub weights: [f32; 2],
+    pub delayed: f32,
+    pub gain: f32,
+    pub output: f32,
+    /// Delay-line slot written after the output was computed.
+    pub write_index: usize,
+    /// Value written there: the input for FIR, the output for IIR.
+    pub written: f32,
+}
+
+/// Named-argument alternative to `CombFilter::new`. Defaults: one channel, gain 0.5,
+/// max delay 1 s and a delay of 0.1 s.
+/// # Example
+/// ```
+/// use ase::{CombFilter, FilterType};
+/// let filter = CombFilter::builder(FilterType::IIR, 44100.0)
+///     .num_channels(2)
+///     .gain(0.7)
+///     .delay_secs(0.02)
+///     .build()
+///     .unwrap();
+/// assert_eq!(filter.num_channels(), 2);
+/// ```
+#[derive(Debug, Clone)]
+pub struct CombFilterBuilder {
+    filter_type: FilterType,
+    sample_rate_hz: f32,
+    num_channels: usize,
+    max_delay_secs: f32,
+    gain: f32,
+    delay_secs: f32,
+}
+
+impl CombFilterBuilder {
+    pub fn num_channels(mut self, num_channels: usize) -> Self {
+        self.num_channels = num_channels;
+        self
+    }
+
+    pub fn max_delay_secs(mut self, max_delay_secs: f32) -> Self {
+        self.max_delay_secs = max_delay_secs;
+        self
+    }
+
+    pub fn gain(mut self, gain: f32) -> Self {
+        self.gain = gain;
+        self
+    }
+
+    pub fn delay_secs(mut self, delay_secs: f32) -> Self {
+        self.delay_secs = delay_secs;
+        self
+    }
+
+    /// Allocates the delay lines; see `CombFilter::new` for validation.
+    pub fn build(self) -> Result<CombFilter, Error> {
+        CombFilter::new(self.filter_type, self.max_delay_secs, self.sample_rate_hz, self.num_channels, self.gain, self.delay_secs)
+    }
+}
+
 impl CombFilter {
+    pub fn builder(filter_type: FilterType, sample_rate_hz: f32) -> CombFilterBuilder {
+        CombFilterBuilder {
+            filter_type,
+            sample_rate_hz,
+            num_channels: 1,
+            max_delay_secs: 1.0,
+            gain: 0.5,
+            delay_secs: 0.1,
+        }
+    }
+
+    /// Allocates a delay line of `max_delay_secs` per channel; not real-time safe.
+    /// Gain must not be negative, and must be below 1 for IIR so the feedback decays.
     pub fn new(
         filter_type: FilterType, 
         max_delay_secs: f32, 
@@ -36,102 +162,527 @@ impl CombFilter {
         gain: f32, 
         delay_secs: f32
     ) -> Result<Self, Error>{
-        if gain < 0.0 {
+        if gain < 0.0 || (gain > MAX_IIR_GAIN && filter_type == FilterType::IIR) {
             return Err(Error::InvalidValue{param: FilterParam::Gain, value: gain})
         }
-        let delay_samples = (delay_secs * sample_rate_hz).round() as usize;
-        if delay_samples > (max_delay_secs * sample_rate_hz).round() as usize {
-            return Err(Error::InvalidValue{param: FilterParam::Delay, value: delay_secs})
-        } else if delay_samples == 0 && filter_type == FilterType::IIR{
+        let delay_samples = secs_to_frac_samples(delay_secs, sample_rate_hz);
+        // IIR needs at least one sample of delay
+        if delay_secs < 0.0 || delay_secs > max_delay_secs || (delay_samples < 1.0 && filter_type == FilterType::IIR) {
             return Err(Error::InvalidValue{param: FilterParam::Delay, value: delay_secs})
         }
-        let buffer = vec![vec![0.0; delay_samples + 1]; num_channels];
+        // Size the delay line for the largest allowed delay so set_param never has to reallocate.
+        // The extra slot holds the second neighbour for fractional delays.
+        let buffer = vec![vec![0.0; delay_line_len(max_delay_secs, sample_rate_hz)]; num_channels];
         let writer_idx = vec![0; num_channels];
+        // Gain changes are instant until a smoothing time is set.
+        let gain_smoothers = (0..num_channels)
+            .map(|_| Smoother::new(SmoothingType::Linear, sample_rate_hz, 0.0, gain))
+            .collect();
+        let delay_smoothers = (0..num_channels)
+            .map(|_| Smoother::new(SmoothingType::Linear, sample_rate_hz, 0.0, delay_samples))
+            .collect();
         Ok(Self{
             max_delay_secs,
             sample_rate_hz,
             num_channels,
             filter_type,
             buffer,
-            gain,
-            delay_samples,
+            fill: 0,
+            gain: vec![gain; num_channels],
+            gain_smoothers,
+            delay_samples: vec![delay_samples; num_channels],
+            delay_smoothers,
+            link_mode: LinkMode::Linked,
             writer_idx,
+            stats: None,
+            callbacks: Callbacks::default(),
+            strict: true,
+            num_recoveries: 0,
+            permissive: false,
+            num_clamped: 0,
         })
     }
 
     pub fn reset(&mut self) {
+        self.fill = 0;
         for channel in &mut self.buffer{
             for sample in channel.iter_mut(){
                 *sample = 0.0;
             }
         }
+        self.gain_smoothers.iter_mut().for_each(|s| s.snap_to_target());
+        self.delay_smoothers.iter_mut().for_each(|s| s.snap_to_target());
     }
 
+    /// Allocation-free; safe to call from an audio callback.
     pub fn process(&mut self, input: &[&[f32]], output: &mut [&mut [f32]]) {
+        self.process_with(input, output, |_, _| {});
+    }
+
+    /// Like `process`, but also returns what happened at every sample of every channel:
+    /// the delay-line read and write, interpolation weights, gain and output. Meant for
+    /// teaching and debugging; it allocates the trace.
+    pub fn process_traced(&mut self, input: &[&[f32]], output: &mut [&mut [f32]]) -> Vec<Vec<SampleTrace>> {
+        let mut traces = vec![Vec::with_capacity(input.first().map_or(0, |channel| channel.len())); input.len()];
+        self.process_with(input, output, |channel, trace| traces[channel].push(trace));
+        traces
+    }
+
+    fn process_with(&mut self, input: &[&[f32]], output: &mut [&mut [f32]], mut on_sample: impl FnMut(usize, SampleTrace)) {
         assert_eq!(input.len(), self.num_channels);
         assert_eq!(output.len(), self.num_channels);
+        let delay_range = self.delay_range();
+        let metering = self.stats.is_some();
         for channel in 0..input.len(){
             let in_channel = input[channel];
             let out_channel = &mut output[channel];
-            if self.buffer[channel].len() > in_channel.len(){
-                panic!("Buffer length is greater than input length");
-            }
+            let mut meter = StatsAccumulator::default();
             for (sample_idx, &input_sample) in in_channel.iter().enumerate(){
                 // comb filter based on filter type
                 // handle ring buffer
-                let delayed_index = (self.writer_idx[channel] + self.buffer[channel].len() - self.delay_samples) % self.buffer[channel].len();
-                // dbg!(&self.writer_idx[channel], delayed_index);
-                // Fetch the delayed sample from the buffer
-                let delayed_sample = self.buffer[channel][delayed_index];
+                // Fetch the delayed sample from the buffer, interpolating between
+                // the two samples either side of a fractional delay
+                // Rounding in the ramp must not step outside the delay line
+                let delay_samples = self.delay_smoothers[channel].next_value().clamp(*delay_range.start(), *delay_range.end());
+                let delay_int = delay_samples.floor() as usize;
+                let frac = delay_samples - delay_int as f32;
+                let current = self.delayed(channel, delay_int, input_sample);
+                let delayed_sample = if frac == 0.0 {
+                    current
+                } else {
+                    let next = self.delayed(channel, delay_int + 1, input_sample);
+                    current + frac * (next - current)
+                };
                 // Calculate the output sample
-                let out_sample = input_sample + self.gain * delayed_sample;
+                let gain = self.gain_smoothers[channel].next_value();
+                let out_sample = input_sample + gain * delayed_sample;
                 // dbg!(input_sample, delayed_sample, out_sample);
                 // dbg!(&out_channel);
                 // Update the output buffer
                 out_channel[sample_idx] = out_sample;
+                if metering {
+                    meter.add(out_sample);
+                }
+                if out_sample.abs() >= 1.0 {
+                    if let Some(on_clip) = &mut self.callbacks.on_clip_detected {
+                        on_clip(channel, sample_idx);
+                    }
+                }
 
 
                 // Update the delay buffer with the current input sample
-                
-                self.buffer[channel][self.writer_idx[channel]] = match self.filter_type {
+                let written = match self.filter_type {
                     FilterType::FIR => input_sample,
                     FilterType::IIR => out_sample,
                 };
+                on_sample(channel, SampleTrace {
+                    input: input_sample,
+                    delay_samples,
+                    weights: [1.0 - frac, frac],
+                    delayed: delayed_sample,
+                    gain,
+                    output: out_sample,
+                    write_index: self.writer_idx[channel],
+                    written,
+                });
+                self.buffer[channel][self.writer_idx[channel]] = written;
                 self.writer_idx[channel] = (self.writer_idx[channel] + 1) % self.buffer[channel].len();
             }
+            let recovered = self.strict && self.recover_non_finite(channel, &mut out_channel[..in_channel.len()]);
+            if let Some(stats) = &mut self.stats {
+                // Recovery changed samples the meter already saw
+                stats[channel] = if recovered { BlockStats::measure(&out_channel[..in_channel.len()]) } else { meter.finish() };
+            }
+        }
+        let block_len = input.first().map_or(0, |channel| channel.len());
+        self.fill = (self.fill + block_len).min(self.buffer.first().map_or(0, Vec::len));
+        if let Some(on_block) = &mut self.callbacks.on_block_processed {
+            on_block(block_len);
         }
     }
 
+    /// Set `param` on every channel, whatever the link mode.
+    /// Allocation-free; the delay line is already sized for the max delay.
     pub fn set_param(&mut self, param: FilterParam, value: f32) -> Result<(), Error> {
+        self.set_param_on(0..self.num_channels, param, value)
+    }
+
+    /// Set `param` on one channel in DualMono mode; in Linked mode this is the same as
+    /// `set_param`. Panics if `channel` is out of range. Allocation-free.
+    pub fn set_channel_param(&mut self, channel: usize, param: FilterParam, value: f32) -> Result<(), Error> {
+        assert!(channel < self.num_channels, "channel {} out of range", channel);
+        match self.link_mode {
+            LinkMode::Linked => self.set_param_on(0..self.num_channels, param, value),
+            LinkMode::DualMono => self.set_param_on(channel..channel + 1, param, value),
+        }
+    }
+
+    fn set_param_on(&mut self, channels: Range<usize>, param: FilterParam, value: f32) -> Result<(), Error> {
+        let value = if self.permissive { self.clamp_param(param, value) } else { value };
         match param {
             FilterParam::Gain => {
-                if value < 0.0 {
-                    return Err(Error::InvalidValue{param, value})
+                if value < 0.0 || (value > MAX_IIR_GAIN && self.filter_type == FilterType::IIR) {
+                    Err(Error::InvalidValue{param, value})
                 } else {
-                    self.gain = value;
+                    self.gain[channels.clone()].fill(value);
+                    self.gain_smoothers[channels].iter_mut().for_each(|s| s.set_target(value));
+                    self.notify_param_changed(param, value);
                     Ok(())
                 }
             },
             FilterParam::Delay => {
-                let delay_samples = (value * self.sample_rate_hz).round() as usize;
-                if delay_samples > (self.max_delay_secs * self.sample_rate_hz).round() as usize{
-                    return Err(Error::InvalidValue{param, value})
-                } else if delay_samples == 0 && self.filter_type == FilterType::IIR{
-                    return Err(Error::InvalidValue{param, value})
+                let delay_samples = secs_to_frac_samples(value, self.sample_rate_hz);
+                if value < 0.0 || value > self.max_delay_secs || (delay_samples < 1.0 && self.filter_type == FilterType::IIR) {
+                    Err(Error::InvalidValue{param, value})
                 } else {
-                    self.delay_samples = delay_samples;
+                    self.delay_samples[channels.clone()].fill(delay_samples);
+                    self.delay_smoothers[channels].iter_mut().for_each(|s| s.set_target(delay_samples));
+                    self.notify_param_changed(param, value);
                     Ok(())
                 }
             }
         }
     }
 
+    /// Switching to Linked makes every channel glide to channel 0's settings.
+    pub fn set_link_mode(&mut self, link_mode: LinkMode) {
+        self.link_mode = link_mode;
+        if let (LinkMode::Linked, Some(&gain), Some(&delay_samples)) = (link_mode, self.gain.first(), self.delay_samples.first()) {
+            self.gain.fill(gain);
+            self.delay_samples.fill(delay_samples);
+            self.gain_smoothers.iter_mut().for_each(|s| s.set_target(gain));
+            self.delay_smoothers.iter_mut().for_each(|s| s.set_target(delay_samples));
+        }
+    }
+
+    pub fn link_mode(&self) -> LinkMode {
+        self.link_mode
+    }
+
+    /// In permissive mode set_param clamps out-of-range values to the nearest valid value
+    /// instead of returning an error, and counts them in `num_clamped`; the parameter-change
+    /// callback gets the clamped value. Useful for automation streams that briefly overshoot.
+    pub fn set_permissive(&mut self, permissive: bool) {
+        self.permissive = permissive;
+    }
+
+    /// Number of set_param values that were clamped in permissive mode.
+    pub fn num_clamped(&self) -> usize {
+        self.num_clamped
+    }
+
+    fn clamp_param(&mut self, param: FilterParam, value: f32) -> f32 {
+        let clamped = match param {
+            FilterParam::Gain => match self.filter_type {
+                FilterType::FIR => value.max(0.0),
+                FilterType::IIR => value.clamp(0.0, MAX_IIR_GAIN),
+            },
+            FilterParam::Delay => {
+                // IIR needs at least one sample of delay
+                let min_delay_secs = match self.filter_type {
+                    FilterType::FIR => 0.0,
+                    FilterType::IIR => 1.0 / self.sample_rate_hz,
+                };
+                value.max(min_delay_secs).min(self.max_delay_secs)
+            }
+        };
+        if clamped != value {
+            self.num_clamped += 1;
+        }
+        clamped
+    }
+
+    /// Value on channel 0; see `get_channel_param` for the other channels in DualMono mode.
     pub fn get_param(&self, param: FilterParam) -> f32 {
+        self.get_channel_param(0, param)
+    }
+
+    pub fn get_channel_param(&self, channel: usize, param: FilterParam) -> f32 {
         match param {
-            FilterParam::Gain => self.gain,
-            FilterParam::Delay => self.delay_samples as f32 / self.sample_rate_hz,
+            FilterParam::Gain => self.gain[channel],
+            FilterParam::Delay => self.delay_samples[channel] / self.sample_rate_hz,
         }
     }
+
+    /// The dry signal reaches the output in the same sample, so a comb filter adds no
+    /// latency; the delay only shapes the sound.
+    pub fn latency_samples(&self) -> usize {
+        0
+    }
+
+    // Delays in samples the delay line can serve; IIR needs at least one sample
+    fn delay_range(&self) -> RangeInclusive<f32> {
+        let min_delay = match self.filter_type {
+            FilterType::FIR => 0.0,
+            FilterType::IIR => 1.0,
+        };
+        let max_delay = self.buffer.first().map_or(0, |channel| channel.len().saturating_sub(2));
+        min_delay..=max_delay as f32
+    }
+
+    // Sample written `delay` samples ago; a delay of zero is the current input.
+    fn delayed(&self, channel: usize, delay: usize, input_sample: f32) -> f32 {
+        if delay == 0 {
+            return input_sample;
+        }
+        let len = self.buffer[channel].len();
+        self.buffer[channel][(self.writer_idx[channel] + len - delay) % len]
+    }
+
+    /// In strict mode (the default in every build profile) every output block is checked
+    /// for NaN/Inf. Bad samples are replaced with silence and that channel's delay line is
+    /// cleared so one bad value can't keep circulating through the feedback path.
+    pub fn set_strict(&mut self, strict: bool) {
+        self.strict = strict;
+    }
+
+    /// Number of channel blocks strict mode has recovered from non-finite output.
+    pub fn num_recoveries(&self) -> usize {
+        self.num_recoveries
+    }
+
+    // Whether the block had non-finite samples to replace
+    fn recover_non_finite(&mut self, channel: usize, block: &mut [f32]) -> bool {
+        if block.iter().all(|x| x.is_finite()) {
+            return false;
+        }
+        self.num_recoveries += 1;
+        block.iter_mut().filter(|x| !x.is_finite()).for_each(|x| *x = 0.0);
+        self.buffer[channel].iter_mut().for_each(|x| *x = 0.0);
+        true
+    }
+
+    /// Called at the end of every process() call with the block length in samples per channel.
+    pub fn set_on_block_processed(&mut self, callback: impl FnMut(usize) + Send + 'static) {
+        self.callbacks.on_block_processed = Some(Box::new(callback));
+    }
+
+    /// Called whenever set_param() accepts a new value.
+    pub fn set_on_param_changed(&mut self, callback: impl FnMut(FilterParam, f32) + Send + 'static) {
+        self.callbacks.on_param_changed = Some(Box::new(callback));
+    }
+
+    /// Called with `(channel, sample index)` for every output sample at or above full scale.
+    pub fn set_on_clip_detected(&mut self, callback: impl FnMut(usize, usize) + Send + 'static) {
+        self.callbacks.on_clip_detected = Some(Box::new(callback));
+    }
+
+    pub fn clear_callbacks(&mut self) {
+        self.callbacks = Callbacks::default();
+    }
+
+    fn notify_param_changed(&mut self, param: FilterParam, value: f32) {
+        if let Some(on_param) = &mut self.callbacks.on_param_changed {
+            on_param(param, value);
+        }
+    }
+
+    /// Turn per-block output metering on or off. The output is measured while it is produced.
+    pub fn set_metering(&mut self, enabled: bool) {
+        self.stats = if enabled { Some(vec![BlockStats::default(); self.num_channels]) } else { None };
+    }
+
+    /// Switch to a new sample rate, keeping the delay and max delay in seconds. The delay
+    /// lines are reallocated for the new rate and cleared, so this is not real-time safe;
+    /// call it from the setup thread, e.g. when the device or file changes. Fails without
+    /// changing anything if the delay is below one sample of an IIR filter at the new rate.
+    pub fn set_sample_rate(&mut self, sample_rate_hz: f32) -> Result<(), Error> {
+        let mut delays = Vec::with_capacity(self.num_channels);
+        for channel in 0..self.num_channels {
+            let delay_secs = self.get_channel_param(channel, FilterParam::Delay);
+            let delay_samples = secs_to_frac_samples(delay_secs, sample_rate_hz);
+            if delay_samples < 1.0 && self.filter_type == FilterType::IIR {
+                return Err(Error::InvalidValue{param: FilterParam::Delay, value: delay_secs})
+            }
+            delays.push(delay_samples);
+        }
+        self.buffer = vec![vec![0.0; delay_line_len(self.max_delay_secs, sample_rate_hz)]; self.num_channels];
+        self.writer_idx.iter_mut().for_each(|idx| *idx = 0);
+        self.sample_rate_hz = sample_rate_hz;
+        for smoother in self.gain_smoothers.iter_mut().chain(self.delay_smoothers.iter_mut()) {
+            smoother.set_sample_rate(sample_rate_hz);
+        }
+        for (smoother, &delay_samples) in self.delay_smoothers.iter_mut().zip(&delays) {
+            smoother.set_target(delay_samples);
+        }
+        self.delay_samples = delays;
+        self.reset();
+        Ok(())
+    }
+
+    /// Output level stats for each channel of the last processed block, if metering is enabled.
+    pub fn block_stats(&self) -> Option<&[BlockStats]> {
+        self.stats.as_deref()
+    }
+
+    /// Ramp gain and delay changes over `time_secs` instead of jumping, so parameters can
+    /// be automated while audio is running without zipper noise or clicks. The delay line
+    /// keeps its contents; only the read position glides to the new delay.
+    pub fn set_smoothing_time(&mut self, time_secs: f32) {
+        self.gain_smoothers.iter_mut().for_each(|s| s.set_time(time_secs));
+        self.delay_smoothers.iter_mut().for_each(|s| s.set_time(time_secs));
+    }
+
+    // Read-only views of the internal state, so callers don't need the private fields.
+    pub fn filter_type(&self) -> FilterType {
+        self.filter_type
+    }
+
+    pub fn sample_rate(&self) -> f32 {
+        self.sample_rate_hz
+    }
+
+    pub fn num_channels(&self) -> usize {
+        self.num_channels
+    }
+
+    pub fn max_delay_secs(&self) -> f32 {
+        self.max_delay_secs
+    }
+
+    /// Delay in samples at this moment, which lags the set value while a change is being smoothed.
+    pub fn current_delay_samples(&self) -> f32 {
+        self.delay_smoothers.first().map_or(0.0, |s| s.current())
+    }
+
+    /// Samples written to each channel's delay line since the last reset (or
+    /// sample rate change), up to the delay line's length.
+    pub fn buffer_fill(&self) -> usize {
+        self.fill
+    }
+}
+
+/// Samples in each channel's delay line: room for `max_delay_secs`, rounded up so a
+/// fractional maximum fits, plus the extra slot a fractional delay reads.
+pub(crate) fn delay_line_len(max_delay_secs: f32, sample_rate_hz: f32) -> usize {
+    secs_to_frac_samples(max_delay_secs, sample_rate_hz).ceil() as usize + 2
+}
+
+// Split `gain`, `gain.1` or `delay.R` into the parameter and an optional channel
+fn parse_channel_param(name: &str, num_channels: usize) -> Option<(FilterParam, Option<usize>)> {
+    let Some((param, channel)) = name.split_once('.') else {
+        return FilterParam::from_name(name).map(|param| (param, None));
+    };
+    let channel = match channel {
+        "L" => 0,
+        "R" => 1,
+        _ => channel.parse().ok()?,
+    };
+    if channel >= num_channels {
+        return None;
+    }
+    Some((FilterParam::from_name(param)?, Some(channel)))
+}
+
+impl FilterParam {
+    pub fn name(&self) -> &'static str {
+        match self {
+            FilterParam::Gain => "gain",
+            FilterParam::Delay => "delay",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "gain" => Some(FilterParam::Gain),
+            "delay" => Some(FilterParam::Delay),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::InvalidValue { param, value } => write!(f, "invalid {} value: {}", param.name(), value),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl AudioEffect for CombFilter {
+    fn process(&mut self, input: &[&[f32]], output: &mut [&mut [f32]]) {
+        CombFilter::process(self, input, output)
+    }
+
+    fn reset(&mut self) {
+        CombFilter::reset(self)
+    }
+
+    // Delay lines with their write positions, then the smoothers
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.put_u64(self.fill as u64);
+        for (buffer, &writer_idx) in self.buffer.iter().zip(&self.writer_idx) {
+            writer.put_u64(writer_idx as u64);
+            writer.put_f32s(buffer);
+        }
+        for smoother in self.gain_smoothers.iter().chain(&self.delay_smoothers) {
+            smoother.save_state(writer);
+        }
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), crate::Error> {
+        let fill = reader.u64()? as usize;
+        for (buffer, writer_idx) in self.buffer.iter_mut().zip(self.writer_idx.iter_mut()) {
+            let index = reader.u64()? as usize;
+            reader.f32s_into(buffer)?;
+            if index >= buffer.len() {
+                return Err(state::state_error(format!("write position {} outside a delay line of {}", index, buffer.len())));
+            }
+            *writer_idx = index;
+        }
+        if fill > self.buffer.first().map_or(0, Vec::len) {
+            return Err(state::state_error(format!("fill of {} samples larger than the delay line", fill)));
+        }
+        self.fill = fill;
+        for smoother in self.gain_smoothers.iter_mut().chain(self.delay_smoothers.iter_mut()) {
+            smoother.load_state(reader)?;
+        }
+        // A delay ramp saved from another filter could read outside this one's delay line
+        let delay_range = self.delay_range();
+        for smoother in &self.delay_smoothers {
+            if let Some(delay) = [smoother.current(), smoother.target()].into_iter().find(|delay| !delay_range.contains(delay)) {
+                return Err(state::state_error(format!("delay of {} samples outside the delay line", delay)));
+            }
+        }
+        Ok(())
+    }
+
+    fn num_channels(&self) -> usize {
+        self.num_channels
+    }
+
+    fn latency_samples(&self) -> usize {
+        CombFilter::latency_samples(self)
+    }
+
+    fn num_clamped(&self) -> usize {
+        CombFilter::num_clamped(self)
+    }
+
+    fn block_stats(&self) -> Option<&[BlockStats]> {
+        CombFilter::block_stats(self)
+    }
+
+    fn param_names(&self) -> &'static [&'static str] {
+        &["gain", "delay"]
+    }
+
+    /// Besides the plain names, `gain.<channel>` and `delay.<channel>` address one
+    /// channel, with `L` and `R` accepted for channels 0 and 1.
+    fn set_param_by_name(&mut self, name: &str, value: f32) -> Result<(), crate::Error> {
+        let unknown = || crate::Error::UnknownParam { name: name.to_string() };
+        match parse_channel_param(name, self.num_channels).ok_or_else(unknown)? {
+            (param, None) => Ok(self.set_param(param, value)?),
+            (param, Some(channel)) => Ok(self.set_channel_param(channel, param, value)?),
+        }
+    }
+
+    fn get_param_by_name(&self, name: &str) -> Option<f32> {
+        let (param, channel) = parse_channel_param(name, self.num_channels)?;
+        Some(self.get_channel_param(channel.unwrap_or(0), param))
+    }
 }
 
     // TODO: feel free to define other functions for your own use
@@ -186,3 +737,274 @@ impl CombFilter {
 //         assert_eq!(output, expected_output, "The output signal did not match the expected output.");
 //     }
 // }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::drift::Drift;
+    use crate::render::FilterSettings;
+
+    #[test]
+    fn test_fractional_delay_impulse() {
+        // A sample rate of 1 Hz makes delay_secs equal to the delay in samples
+        let mut filter = CombFilter::new(FilterType::FIR, 32.0, 1.0, 1, 1.0, 10.5).expect("Failed to create CombFilter");
+        let mut input = vec![vec![0.0; 16]; 1];
+        input[0][0] = 1.0;
+        let mut output = vec![vec![0.0; 16]; 1];
+
+        filter.process(&input.iter().map(|x| &x[..]).collect::<Vec<_>>(), &mut output.iter_mut().map(|x| &mut x[..]).collect::<Vec<_>>());
+
+        // The delayed impulse is split evenly between samples 10 and 11
+        let mut expected = vec![0.0; 16];
+        expected[0] = 1.0;
+        expected[10] = 0.5;
+        expected[11] = 0.5;
+        assert_eq!(output[0], expected, "Fractional delay test failed: impulse not split across neighbouring samples.");
+        assert_eq!(filter.get_param(FilterParam::Delay), 10.5);
+    }
+
+    #[test]
+    fn test_non_finite_input_recovery() {
+        // Strict mode is on by default, whatever the build profile
+        let mut filter = CombFilter::new(FilterType::IIR, 1.0, 10.0, 1, 0.5, 0.3).expect("Failed to create CombFilter");
+        let mut input = vec![vec![0.0; 8]; 1];
+        input[0][2] = f32::NAN;
+        let mut output = vec![vec![0.0; 8]; 1];
+
+        filter.process(&input.iter().map(|x| &x[..]).collect::<Vec<_>>(), &mut output.iter_mut().map(|x| &mut x[..]).collect::<Vec<_>>());
+        assert!(output[0].iter().all(|x| x.is_finite()), "NaN recovery test failed: non-finite output was not replaced.");
+        assert_eq!(filter.num_recoveries(), 1);
+
+        // The NaN must not survive in the feedback path
+        let input = vec![vec![1.0; 8]; 1];
+        filter.process(&input.iter().map(|x| &x[..]).collect::<Vec<_>>(), &mut output.iter_mut().map(|x| &mut x[..]).collect::<Vec<_>>());
+        assert!(output[0].iter().all(|x| x.is_finite()), "NaN recovery test failed: filter state was not reset.");
+        assert_eq!(filter.num_recoveries(), 1);
+
+        // Without strict mode the NaN passes through and keeps circulating
+        let mut filter = CombFilter::new(FilterType::IIR, 1.0, 10.0, 1, 0.5, 0.3).expect("Failed to create CombFilter");
+        filter.set_strict(false);
+        let mut input = vec![vec![0.0; 8]; 1];
+        input[0][2] = f32::NAN;
+        filter.process(&input.iter().map(|x| &x[..]).collect::<Vec<_>>(), &mut output.iter_mut().map(|x| &mut x[..]).collect::<Vec<_>>());
+        assert!(output[0][2].is_nan() && output[0][5].is_nan(), "Non-strict test failed: NaN was replaced or didn't reach the feedback path.");
+        assert_eq!(filter.num_recoveries(), 0);
+    }
+
+    #[test]
+    fn test_permissive_mode_clamps_and_counts() {
+        let mut filter = CombFilter::new(FilterType::FIR, 0.1, 1000.0, 1, 0.5, 0.01).expect("Failed to create CombFilter");
+        assert!(filter.set_param(FilterParam::Delay, 0.2).is_err());
+        filter.set_permissive(true);
+        let changes = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let seen = changes.clone();
+        filter.set_on_param_changed(move |param, value| seen.lock().unwrap().push((param, value)));
+        filter.set_param(FilterParam::Delay, 0.2).expect("Permissive mode should clamp");
+        filter.set_param(FilterParam::Gain, -1.0).expect("Permissive mode should clamp");
+        filter.set_param(FilterParam::Gain, 0.7).expect("Failed to set gain");
+        assert_eq!(filter.num_clamped(), 2);
+        assert_eq!(*changes.lock().unwrap(), [(FilterParam::Delay, 0.1), (FilterParam::Gain, 0.0), (FilterParam::Gain, 0.7)]);
+    }
+
+    #[test]
+    fn test_smoothed_delay_change_has_no_jump() {
+        // Largest sample-to-sample step in the block after the delay changes
+        let max_step_after_delay_change = |smoothing_secs: f32| {
+            let mut filter = CombFilter::new(FilterType::FIR, 0.1, 1000.0, 1, 1.0, 0.01).expect("Failed to create CombFilter");
+            filter.set_smoothing_time(smoothing_secs);
+            let sine: Vec<f32> = (0..400).map(|n| (2.0 * std::f32::consts::PI * 10.0 * n as f32 / 1000.0).sin()).collect();
+            let mut output = vec![vec![0.0; 200]; 1];
+            filter.process(&[&sine[..200]], &mut output.iter_mut().map(|x| &mut x[..]).collect::<Vec<_>>());
+            let last = output[0][199];
+            filter.set_param(FilterParam::Delay, 0.06).expect("Failed to set delay");
+            filter.process(&[&sine[200..]], &mut output.iter_mut().map(|x| &mut x[..]).collect::<Vec<_>>());
+            std::iter::once(last).chain(output[0].iter().copied()).collect::<Vec<_>>().windows(2).map(|w| (w[1] - w[0]).abs()).fold(0.0, f32::max)
+        };
+        let jump = max_step_after_delay_change(0.0);
+        let smoothed = max_step_after_delay_change(0.05);
+        assert!(smoothed < 0.5 * jump, "Smoothed delay test failed: step {} is not much smaller than unsmoothed step {}.", smoothed, jump);
+    }
+
+    #[test]
+    fn test_smoothed_delay_stays_within_delay_line() {
+        // One sample to the full 100 samples and back, over a ramp that doesn't divide the distance evenly
+        let mut filter = CombFilter::new(FilterType::IIR, 0.1, 1000.0, 1, 0.5, 0.001).expect("Failed to create CombFilter");
+        filter.set_smoothing_time(0.037);
+        let input = vec![vec![0.5; 200]; 1];
+        let mut output = vec![vec![0.0; 200]; 1];
+        let mut delays = Vec::new();
+        for delay_secs in [0.1, 0.001] {
+            filter.set_param(FilterParam::Delay, delay_secs).expect("Failed to set delay");
+            let traces = filter.process_traced(&input.iter().map(|x| &x[..]).collect::<Vec<_>>(), &mut output.iter_mut().map(|x| &mut x[..]).collect::<Vec<_>>());
+            delays.extend(traces[0].iter().map(|trace| trace.delay_samples));
+        }
+        assert!(delays.iter().all(|delay| (1.0..=100.0).contains(delay)), "Delay ramp left the delay line");
+        assert_eq!((delays[199], delays[399]), (100.0, 1.0));
+
+        // A state whose delay is below one sample can't be loaded into an IIR filter
+        let fir = CombFilter::new(FilterType::FIR, 0.1, 1000.0, 1, 0.5, 0.0).expect("Failed to create CombFilter");
+        let mut writer = state::StateWriter::new();
+        fir.save_state(&mut writer);
+        let bytes = writer.into_bytes();
+        assert!(matches!(filter.load_state(&mut state::StateReader::new(&bytes)), Err(crate::Error::State { .. })));
+    }
+
+    #[test]
+    fn test_iir_gain_must_be_below_one() {
+        assert!(CombFilter::new(FilterType::IIR, 0.1, 1000.0, 1, 1.0, 0.01).is_err());
+        assert!(CombFilter::new(FilterType::FIR, 0.1, 1000.0, 1, 1.5, 0.01).is_ok());
+        let mut filter = CombFilter::new(FilterType::IIR, 0.1, 1000.0, 1, 0.5, 0.01).expect("Failed to create CombFilter");
+        assert!(filter.set_param(FilterParam::Gain, 1.0).is_err());
+        filter.set_permissive(true);
+        filter.set_param(FilterParam::Gain, 1.5).expect("Permissive mode should clamp");
+        assert!(filter.get_param(FilterParam::Gain) < 1.0);
+
+        // Drift can't push a gain just below 1 over it
+        let mut filters = vec![FilterSettings { filter_type: FilterType::IIR, max_delay_secs: 0.1, sample_rate_hz: 1000.0, gain: 0.999, delay_secs: 0.01 }; 8];
+        Drift::new(1, 0.5).unwrap().apply(&mut filters, &[]);
+        assert!(filters.iter().all(|settings| settings.gain < 1.0));
+    }
+
+    #[test]
+    fn test_traced_processing_matches_process() {
+        let input: Vec<f32> = (0..64).map(|n| (n as f32 * 0.3).sin()).collect();
+        let mut plain = CombFilter::new(FilterType::IIR, 0.01, 1000.0, 1, 0.5, 0.0025).expect("Failed to create CombFilter");
+        let mut traced = CombFilter::new(FilterType::IIR, 0.01, 1000.0, 1, 0.5, 0.0025).expect("Failed to create CombFilter");
+        let mut plain_output = vec![0.0; 64];
+        let mut traced_output = vec![0.0; 64];
+        plain.process(&[&input], &mut [&mut plain_output]);
+        let trace = traced.process_traced(&[&input], &mut [&mut traced_output]);
+        assert_eq!(plain_output, traced_output);
+        assert_eq!(trace[0].len(), 64);
+        // 2.5 samples of delay reads halfway between the samples 2 and 3 back
+        let step = trace[0][10];
+        assert_eq!(step.weights, [0.5, 0.5]);
+        assert!((step.delayed - 0.5 * (traced_output[8] + traced_output[7])).abs() < 1e-6, "Trace test failed: delayed value doesn't match the weights.");
+        assert_eq!((step.write_index, step.written), (10, traced_output[10]));
+    }
+
+    #[test]
+    fn test_dual_mono_parameters_are_independent() {
+        let mut impulse = vec![0.0; 64];
+        impulse[0] = 1.0;
+        let echoes = |filter: &mut CombFilter| {
+            let mut output = vec![vec![0.0; 64]; 2];
+            filter.process(&[&impulse, &impulse], &mut output.iter_mut().map(|x| &mut x[..]).collect::<Vec<_>>());
+            filter.reset();
+            output.iter().map(|channel| channel.iter().rposition(|&x| x != 0.0).unwrap_or(0)).collect::<Vec<_>>()
+        };
+
+        // Linked: a per-channel setting still moves both channels
+        let mut filter = CombFilter::new(FilterType::FIR, 0.001, 44100.0, 2, 0.5, 10.0 / 44100.0).expect("Failed to create CombFilter");
+        filter.set_channel_param(1, FilterParam::Delay, 20.0 / 44100.0).expect("Failed to set delay");
+        assert_eq!(echoes(&mut filter), [20, 20]);
+
+        // Dual mono: each channel keeps its own delay and gain, also when addressed by name
+        filter.set_link_mode(LinkMode::DualMono);
+        filter.set_param_by_name("delay.L", 10.0 / 44100.0).expect("Failed to set delay.L");
+        filter.set_param_by_name("gain.1", 0.25).expect("Failed to set gain.1");
+        assert_eq!(echoes(&mut filter), [10, 20]);
+        assert_eq!(filter.get_param_by_name("gain.R"), Some(0.25));
+        assert_eq!(filter.get_param_by_name("gain.L"), Some(0.5));
+        assert!(filter.set_param_by_name("gain.2", 0.5).is_err(), "Link mode test failed: accepted a missing channel.");
+
+        // Back to linked: channel 1 follows channel 0 again
+        filter.set_link_mode(LinkMode::Linked);
+        assert_eq!(echoes(&mut filter), [10, 10]);
+        assert_eq!(filter.get_channel_param(1, FilterParam::Gain), 0.5);
+    }
+
+    #[test]
+    fn test_set_sample_rate_keeps_delay_in_seconds() {
+        // 10 ms is 441 samples at 44.1 kHz and 480 samples at 48 kHz
+        let mut filter = CombFilter::new(FilterType::FIR, 0.02, 44100.0, 1, 1.0, 0.01).expect("Failed to create CombFilter");
+        filter.set_sample_rate(48000.0).expect("Failed to change sample rate");
+        assert_eq!(filter.sample_rate(), 48000.0);
+        assert!((filter.get_param(FilterParam::Delay) - 0.01).abs() < 1e-6, "Sample rate test failed: delay changed in seconds.");
+        let mut impulse = vec![0.0; 1024];
+        impulse[0] = 1.0;
+        let mut output = vec![vec![0.0; 1024]; 1];
+        filter.process(&[&impulse], &mut output.iter_mut().map(|x| &mut x[..]).collect::<Vec<_>>());
+        let echoes: Vec<usize> = output[0].iter().enumerate().filter(|(_, &x)| x != 0.0).map(|(n, _)| n).collect();
+        assert_eq!(echoes, [0, 480]);
+
+        // A one-sample IIR delay at 44.1 kHz is less than a sample at 22.05 kHz
+        let mut filter = CombFilter::new(FilterType::IIR, 0.02, 44100.0, 1, 0.5, 1.0 / 44100.0).expect("Failed to create CombFilter");
+        assert!(filter.set_sample_rate(22050.0).is_err());
+        assert_eq!(filter.sample_rate(), 44100.0);
+    }
+
+    #[test]
+    fn test_long_delay_in_small_blocks() {
+        // 100 samples of delay, fed 16 samples at a time; the delay line, not the block, holds the delay
+        for filter_type in [FilterType::FIR, FilterType::IIR] {
+            let mut filter = CombFilter::new(filter_type, 0.2, 1000.0, 1, 0.5, 0.1).expect("Failed to create CombFilter");
+            let mut impulse = vec![0.0; 320];
+            impulse[0] = 1.0;
+            let mut output = vec![0.0; 320];
+            for (input, output) in impulse.chunks(16).zip(output.chunks_mut(16)) {
+                filter.process(&[input], &mut [output]);
+            }
+            let echoes: Vec<(usize, f32)> = output.iter().copied().enumerate().filter(|&(_, x)| x != 0.0).collect();
+            let expected: &[(usize, f32)] = match filter_type {
+                FilterType::FIR => &[(0, 1.0), (100, 0.5)],
+                FilterType::IIR => &[(0, 1.0), (100, 0.5), (200, 0.25), (300, 0.125)],
+            };
+            assert_eq!(echoes, expected, "Long delay test failed: {:?} echoes misplaced with 16-sample blocks.", filter_type);
+        }
+    }
+
+    #[test]
+    fn test_buffer_fill_counts_written_samples() {
+        // 100 ms at 1 kHz is a 102-sample delay line
+        let mut filter = CombFilter::new(FilterType::IIR, 0.1, 1000.0, 2, 0.5, 0.01).expect("Failed to create CombFilter");
+        assert_eq!(filter.buffer_fill(), 0);
+        let input = vec![vec![0.25; 64]; 2];
+        let mut output = vec![vec![0.0; 64]; 2];
+        let process = |filter: &mut CombFilter, output: &mut Vec<Vec<f32>>, len: usize| {
+            filter.process(&input.iter().map(|x| &x[..len]).collect::<Vec<_>>(), &mut output.iter_mut().map(|x| &mut x[..len]).collect::<Vec<_>>());
+        };
+        process(&mut filter, &mut output, 10);
+        assert_eq!(filter.buffer_fill(), 10);
+        process(&mut filter, &mut output, 64);
+        assert_eq!(filter.buffer_fill(), 74);
+        process(&mut filter, &mut output, 64);
+        assert_eq!(filter.buffer_fill(), 102);
+        filter.reset();
+        assert_eq!(filter.buffer_fill(), 0);
+        process(&mut filter, &mut output, 0);
+        assert_eq!(filter.buffer_fill(), 0);
+    }
+
+    #[test]
+    fn test_fractional_max_delay_is_reachable() {
+        // 10.4 samples of maximum delay would round down to 10; the delay line must still reach it
+        let mut filter = CombFilter::new(FilterType::FIR, 10.4, 1.0, 1, 1.0, 10.4).expect("Failed to create CombFilter");
+        let mut impulse = vec![0.0; 16];
+        impulse[0] = 1.0;
+        let mut output = vec![vec![0.0; 16]; 1];
+        let trace = filter.process_traced(&[&impulse], &mut output.iter_mut().map(|x| &mut x[..]).collect::<Vec<_>>());
+        assert!(trace[0].iter().all(|step| (step.delay_samples - 10.4).abs() < 1e-5), "Max delay test failed: delay clamped below 10.4 samples.");
+        assert!((output[0][10] - 0.6).abs() < 1e-5 && (output[0][11] - 0.4).abs() < 1e-5, "Max delay test failed: echo not split 60/40 across samples 10 and 11.");
+    }
+
+    #[test]
+    fn test_metering_matches_measured_output() {
+        let mut filter = CombFilter::new(FilterType::IIR, 0.01, 1000.0, 2, 0.9, 0.003).expect("Failed to create CombFilter");
+        filter.set_metering(true);
+        let input: Vec<Vec<f32>> = (0..2).map(|c| (0..64).map(|n| 0.8 * ((n + c) as f32 * 0.4).sin()).collect()).collect();
+        let mut output = vec![vec![0.0; 64]; 2];
+        filter.process(&input.iter().map(|x| &x[..]).collect::<Vec<_>>(), &mut output.iter_mut().map(|x| &mut x[..]).collect::<Vec<_>>());
+        let expected: Vec<BlockStats> = output.iter().map(|channel| BlockStats::measure(channel)).collect();
+        assert!(expected[0].clip_count > 0, "Metering test failed: the block should clip.");
+        assert_eq!(filter.block_stats(), Some(&expected[..]));
+
+        // Recovered samples are metered as written
+        filter.set_strict(true);
+        let mut input = input;
+        input[1][5] = f32::INFINITY;
+        filter.process(&input.iter().map(|x| &x[..]).collect::<Vec<_>>(), &mut output.iter_mut().map(|x| &mut x[..]).collect::<Vec<_>>());
+        let expected: Vec<BlockStats> = output.iter().map(|channel| BlockStats::measure(channel)).collect();
+        assert_eq!(filter.block_stats(), Some(&expected[..]));
+    }
+}
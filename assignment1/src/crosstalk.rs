@@ -0,0 +1,142 @@
+use crate::effect::AudioEffect;
+use crate::error::Error;
+use crate::state::{StateReader, StateWriter};
+use crate::units::db_to_linear;
+
+/// Channel crosstalk as on tape machines and consoles: every channel picks up a
+/// quiet, lowpassed copy of all the other channels. With one channel it passes the
+/// signal through unchanged.
+pub struct Crosstalk {
+    sample_rate_hz: f32,
+    num_channels: usize,
+    amount_db: f32,
+    cutoff_hz: f32,
+    // Linear bleed gain and one-pole lowpass coefficient derived from the settings above
+    gain: f32,
+    coeff: f32,
+    // Lowpass state per source channel
+    lowpass: Vec<f32>,
+}
+
+impl Crosstalk {
+    /// `amount_db` is the level of the bleed relative to the source (at most 0 dB);
+    /// `cutoff_hz` is the corner of the lowpass the bleed goes through.
+    /// Allocates the filter state; not real-time safe.
+    pub fn new(sample_rate_hz: f32, num_channels: usize, amount_db: f32, cutoff_hz: f32) -> Result<Self, Error> {
+        let mut crosstalk = Crosstalk {
+            sample_rate_hz,
+            num_channels,
+            amount_db: 0.0,
+            cutoff_hz: 0.0,
+            gain: 0.0,
+            coeff: 0.0,
+            lowpass: vec![0.0; num_channels],
+        };
+        crosstalk.set_amount_db(amount_db)?;
+        crosstalk.set_cutoff_hz(cutoff_hz)?;
+        Ok(crosstalk)
+    }
+
+    pub fn set_amount_db(&mut self, amount_db: f32) -> Result<(), Error> {
+        if amount_db > 0.0 || amount_db.is_nan() {
+            return Err(Error::InvalidValue { param: "amount_db", value: amount_db });
+        }
+        self.amount_db = amount_db;
+        self.gain = db_to_linear(amount_db);
+        Ok(())
+    }
+
+    pub fn set_cutoff_hz(&mut self, cutoff_hz: f32) -> Result<(), Error> {
+        if cutoff_hz <= 0.0 || cutoff_hz >= self.sample_rate_hz / 2.0 || cutoff_hz.is_nan() {
+            return Err(Error::InvalidValue { param: "cutoff", value: cutoff_hz });
+        }
+        self.cutoff_hz = cutoff_hz;
+        self.coeff = (-std::f32::consts::TAU * cutoff_hz / self.sample_rate_hz).exp();
+        Ok(())
+    }
+
+    pub fn amount_db(&self) -> f32 {
+        self.amount_db
+    }
+
+    pub fn cutoff_hz(&self) -> f32 {
+        self.cutoff_hz
+    }
+}
+
+impl AudioEffect for Crosstalk {
+    fn process(&mut self, input: &[&[f32]], output: &mut [&mut [f32]]) {
+        assert_eq!(input.len(), self.num_channels);
+        assert_eq!(output.len(), self.num_channels);
+        let block_size = input.first().map_or(0, |channel| channel.len());
+        for n in 0..block_size {
+            // Filter every channel once; each output then gets all filtered channels but its own
+            let mut bleed_sum = 0.0;
+            for (state, channel) in self.lowpass.iter_mut().zip(input) {
+                *state = (1.0 - self.coeff) * channel[n] + self.coeff * *state;
+                bleed_sum += *state;
+            }
+            for ((out, channel), state) in output.iter_mut().zip(input).zip(&self.lowpass) {
+                out[n] = channel[n] + self.gain * (bleed_sum - state);
+            }
+        }
+    }
+
+    fn reset(&mut self) {
+        self.lowpass.iter_mut().for_each(|state| *state = 0.0);
+    }
+
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.put_f32s(&self.lowpass);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), Error> {
+        reader.f32s_into(&mut self.lowpass)
+    }
+
+    fn num_channels(&self) -> usize {
+        self.num_channels
+    }
+
+    fn param_names(&self) -> &'static [&'static str] {
+        &["amount_db", "cutoff"]
+    }
+
+    fn set_param_by_name(&mut self, name: &str, value: f32) -> Result<(), Error> {
+        match name {
+            "amount_db" => self.set_amount_db(value),
+            "cutoff" => self.set_cutoff_hz(value),
+            _ => Err(Error::UnknownParam { name: name.to_string() }),
+        }
+    }
+
+    fn get_param_by_name(&self, name: &str) -> Option<f32> {
+        match name {
+            "amount_db" => Some(self.amount_db),
+            "cutoff" => Some(self.cutoff_hz),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_kit;
+
+    #[test]
+    fn test_crosstalk_bleeds_filtered_signal() {
+        let make_crosstalk = |num_channels| Box::new(Crosstalk::new(44100.0, num_channels, -20.0, 5000.0).unwrap()) as Box<dyn AudioEffect>;
+        test_kit::check_all("crosstalk", &make_crosstalk, &[("amount_db", &[-120.0, -20.0, 0.0]), ("cutoff", &[1.0, 5000.0, 22049.0])]);
+
+        // A DC step on the left channel only reaches the right channel at -20 dB, after the lowpass settles
+        let mut crosstalk = Crosstalk::new(44100.0, 2, -20.0, 5000.0).expect("Failed to create Crosstalk");
+        let left = vec![1.0; 1024];
+        let right = vec![0.0; 1024];
+        let mut output = vec![vec![0.0; 1024]; 2];
+        crosstalk.process(&[&left, &right], &mut output.iter_mut().map(|x| &mut x[..]).collect::<Vec<_>>());
+        assert!(output[0].iter().all(|&x| x == 1.0), "Crosstalk test failed: silent channel bled into the left one.");
+        assert!(output[1][0] > 0.0 && output[1][0] < 0.1, "Crosstalk test failed: bleed not lowpassed.");
+        assert!((output[1][1023] - 0.1).abs() < 1e-4, "Crosstalk test failed: settled bleed {} is not -20 dB.", output[1][1023]);
+    }
+}
@@ -0,0 +1,138 @@
+use std::fmt;
+
+use crate::error::Error;
+
+// Errors and warnings as the binary reports them, either as plain text lines or as one
+// JSON object per line for GUIs and scripts that wrap the binary. Codes are stable;
+// messages are English and may change, so wrappers should key translations and logic
+// on the code.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// One error or warning.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: &'static str,
+    pub message: String,
+    /// The parameter or option the diagnostic is about, if any.
+    pub param: Option<String>,
+    /// What would have been accepted, e.g. `16, 24, 32 or float`.
+    pub expected: Option<String>,
+}
+
+impl Diagnostic {
+    pub fn error(code: &'static str, message: impl Into<String>) -> Self {
+        Diagnostic { severity: Severity::Error, code, message: message.into(), param: None, expected: None }
+    }
+
+    pub fn warning(code: &'static str, message: impl Into<String>) -> Self {
+        Diagnostic { severity: Severity::Warning, ..Diagnostic::error(code, message) }
+    }
+
+    pub fn with_param(mut self, param: impl Into<String>) -> Self {
+        self.param = Some(param.into());
+        self
+    }
+
+    pub fn with_expected(mut self, expected: impl Into<String>) -> Self {
+        self.expected = Some(expected.into());
+        self
+    }
+
+    /// A library error, with `context` (e.g. "Render failed") in front of its message.
+    pub fn from_error(context: &str, err: &Error) -> Self {
+        let diagnostic = Diagnostic::error(err.code(), format!("{}: {}", context, err));
+        match err {
+            Error::InvalidValue { param, .. } => diagnostic.with_param(*param),
+            Error::UnknownParam { name } => diagnostic.with_param(name.as_str()),
+            _ => diagnostic,
+        }
+    }
+
+    /// Single-line JSON object; absent fields are left out.
+    pub fn to_json(&self) -> String {
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        let mut json = format!(
+            "{{\"severity\":\"{}\",\"code\":\"{}\",\"message\":{}",
+            severity,
+            self.code,
+            json_string(&self.message)
+        );
+        if let Some(param) = &self.param {
+            json.push_str(&format!(",\"param\":{}", json_string(param)));
+        }
+        if let Some(expected) = &self.expected {
+            json.push_str(&format!(",\"expected\":{}", json_string(expected)));
+        }
+        json.push('}');
+        json
+    }
+}
+
+/// Text form, as printed without `--error-format json`.
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.severity == Severity::Warning {
+            write!(f, "Warning: ")?;
+        }
+        write!(f, "{}", self.message)?;
+        if let Some(expected) = &self.expected {
+            write!(f, " (expected {})", expected)?;
+        }
+        Ok(())
+    }
+}
+
+fn json_string(text: &str) -> String {
+    let mut quoted = String::with_capacity(text.len() + 2);
+    quoted.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if (c as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::comb_filter::{CombFilter, FilterType};
+
+    #[test]
+    fn test_diagnostic_json_output() {
+        let diagnostic = Diagnostic::error("invalid_option", "Invalid output format: \"8\"").with_param("--out-format").with_expected("16, 24, 32 or float");
+        assert_eq!(
+            diagnostic.to_json(),
+            r#"{"severity":"error","code":"invalid_option","message":"Invalid output format: \"8\"","param":"--out-format","expected":"16, 24, 32 or float"}"#
+        );
+        assert_eq!(diagnostic.to_string(), "Invalid output format: \"8\" (expected 16, 24, 32 or float)");
+
+        let warning = Diagnostic::warning("clipping", "3 output samples clipped");
+        assert_eq!(warning.to_json(), r#"{"severity":"warning","code":"clipping","message":"3 output samples clipped"}"#);
+        assert_eq!(warning.to_string(), "Warning: 3 output samples clipped");
+
+        // Library errors keep their code and name the parameter they are about
+        let err = CombFilter::new(FilterType::IIR, 1.0, 100.0, 1, -0.5, 0.1).err().expect("Negative gain should fail").into();
+        let diagnostic = Diagnostic::from_error("Render failed", &err);
+        assert_eq!((diagnostic.code, diagnostic.param.as_deref()), ("invalid_value", Some("gain")));
+        assert!(diagnostic.message.starts_with("Render failed: "));
+        let unknown = Diagnostic::from_error("Invalid preset", &Error::UnknownParam { name: "feedback".to_string() });
+        assert_eq!((unknown.code, unknown.param.as_deref()), ("unknown_param", Some("feedback")));
+    }
+}
@@ -0,0 +1,129 @@
+use crate::comb_filter::{FilterParam, FilterType, MAX_IIR_GAIN};
+use crate::error::Error;
+use crate::hiss::next_noise;
+use crate::render::FilterSettings;
+
+// "Analog drift": every filter instance in a render gets small random offsets on its
+// parameters, like component tolerances between hardware units. The offsets are
+// seeded, so a batch rendered with the same seed comes out the same every time, and
+// each filter draws from its own stream so adding a filter doesn't move the others.
+
+/// Seed and size of the random parameter offsets.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Drift {
+    pub seed: u64,
+    /// Largest relative deviation, e.g. 0.02 for up to ±2 %.
+    pub amount: f32,
+}
+
+/// Offset applied to one parameter of one filter.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DriftOffset {
+    /// Index into `RenderConfig::filters`.
+    pub filter_index: usize,
+    pub param: FilterParam,
+    /// Value the parameter was set to and the value it got after drifting.
+    pub nominal: f32,
+    pub drifted: f32,
+}
+
+impl Drift {
+    /// `amount` must be between 0 and 1.
+    pub fn new(seed: u64, amount: f32) -> Result<Self, Error> {
+        if !(0.0..1.0).contains(&amount) {
+            return Err(Error::InvalidValue { param: "drift amount", value: amount });
+        }
+        Ok(Drift { seed, amount })
+    }
+
+    /// Parse a `--drift` value: `<seed>` or `<seed>,<amount>`, with a default amount of 2 %.
+    pub fn parse(value: &str) -> Option<Self> {
+        let (seed, amount) = value.split_once(',').unwrap_or((value, "0.02"));
+        Drift::new(seed.parse().ok()?, amount.parse().ok()?).ok()
+    }
+
+    /// Offset gain and delay of every filter in place, skipping the parameters listed
+    /// in `fixed` (e.g. automated ones). Delays and IIR gains stay within each filter's
+    /// valid range.
+    pub fn apply(&self, filters: &mut [FilterSettings], fixed: &[(usize, FilterParam)]) -> Vec<DriftOffset> {
+        let mut offsets = Vec::new();
+        for (filter_index, settings) in filters.iter_mut().enumerate() {
+            // xorshift must not start at zero
+            let mut state = (self.seed ^ (filter_index as u64 + 1).wrapping_mul(0x9E37_79B9_7F4A_7C15)).max(1);
+            for param in [FilterParam::Gain, FilterParam::Delay] {
+                // Draw even for fixed parameters, so fixing one doesn't change the other
+                let factor = 1.0 + self.amount * next_noise(&mut state);
+                if fixed.iter().any(|&(index, p)| index == filter_index && p == param) {
+                    continue;
+                }
+                let (nominal, drifted) = match param {
+                    FilterParam::Gain => {
+                        // IIR gain must stay below 1
+                        let max_gain = match settings.filter_type {
+                            FilterType::FIR => f32::INFINITY,
+                            FilterType::IIR => MAX_IIR_GAIN,
+                        };
+                        (settings.gain, (settings.gain * factor).min(max_gain))
+                    }
+                    FilterParam::Delay => {
+                        // IIR needs at least one sample of delay
+                        let min_delay_secs = match settings.filter_type {
+                            FilterType::FIR => 0.0,
+                            FilterType::IIR => 1.0 / settings.sample_rate_hz,
+                        };
+                        let nominal = settings.delay_secs;
+                        (nominal, (nominal * factor).min(settings.max_delay_secs).max(min_delay_secs.min(nominal)))
+                    }
+                };
+                match param {
+                    FilterParam::Gain => settings.gain = drifted,
+                    FilterParam::Delay => settings.delay_secs = drifted,
+                }
+                offsets.push(DriftOffset { filter_index, param, nominal, drifted });
+            }
+        }
+        offsets
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render::{self, RenderConfig};
+    use crate::test_wav::{temp_path, write_wav};
+
+    #[test]
+    fn test_drift_is_seeded_and_recorded() {
+        let settings = FilterSettings { filter_type: FilterType::IIR, max_delay_secs: 0.01, sample_rate_hz: 44100.0, gain: 0.5, delay_secs: 0.005 };
+        let drifted = |seed: u64, fixed: &[(usize, FilterParam)]| {
+            let mut filters = vec![settings.clone(); 3];
+            let offsets = Drift::new(seed, 0.05).unwrap().apply(&mut filters, fixed);
+            (filters, offsets)
+        };
+        let (filters, offsets) = drifted(1, &[]);
+        assert_eq!(offsets.len(), 6);
+        for offset in &offsets {
+            assert!((offset.drifted / offset.nominal - 1.0).abs() <= 0.05, "Drift test failed: offset {:?} larger than 5%.", offset);
+        }
+        // Same seed, same offsets; each filter instance gets its own
+        assert_eq!(drifted(1, &[]).1, offsets);
+        assert_ne!(drifted(2, &[]).1, offsets);
+        assert_ne!(filters[0].gain, filters[1].gain);
+        // A fixed parameter keeps its value and doesn't shift the other offsets
+        let (filters, fixed_offsets) = drifted(1, &[(1, FilterParam::Delay)]);
+        assert_eq!(filters[1].delay_secs, 0.005);
+        assert_eq!(fixed_offsets.len(), 5);
+        assert!(fixed_offsets.iter().all(|offset| offsets.contains(offset)));
+        assert!(Drift::new(1, 1.0).is_err());
+        assert_eq!(Drift::parse("7"), Some(Drift { seed: 7, amount: 0.02 }));
+
+        // The render report lists what the render used
+        let input_path = write_wav("impulse.wav", 1, 44100, (0..2048).map(|n| if n == 0 { 1.0 } else { 0.0 }));
+        let config = RenderConfig {
+            drift: Some(Drift::new(1, 0.05).unwrap()),
+            ..RenderConfig::new(input_path, temp_path("drift.wav"), vec![settings.clone()])
+        };
+        let report = render::run(&config).expect("Failed to render");
+        assert_eq!(report.drift_offsets, drifted(1, &[]).1[..2]);
+    }
+}
@@ -0,0 +1,359 @@
+use crate::error::Error;
+use crate::meter::BlockStats;
+use crate::state::{self, StateReader, StateWriter};
+
+/// Common interface of the crate's processors, so they can be run in an `EffectChain`.
+/// Parameters are addressed by name here; each effect also keeps its own typed
+/// `set_param`/`get_param` for direct use.
+pub trait AudioEffect {
+    /// Process one block per channel. `input` and `output` hold `num_channels()` slices.
+    fn process(&mut self, input: &[&[f32]], output: &mut [&mut [f32]]);
+
+    /// Clear all internal state (delay lines, smoothers) without changing parameters.
+    fn reset(&mut self);
+
+    fn num_channels(&self) -> usize;
+
+    /// Delay (in samples) the effect adds to the signal path, for hosts that compensate
+    /// latency. Effects whose dry signal passes through immediately report 0.
+    fn latency_samples(&self) -> usize {
+        0
+    }
+
+    /// Parameter values clamped to the valid range instead of rejected, for effects with
+    /// a permissive mode. Effects without one report 0.
+    fn num_clamped(&self) -> usize {
+        0
+    }
+
+    /// Output level stats of each channel of the last processed block, for effects that
+    /// meter their output while processing it. `None` if the effect doesn't meter.
+    fn block_stats(&self) -> Option<&[BlockStats]> {
+        None
+    }
+
+    /// Append the internal state (what `reset` clears) to `writer`, for continuing
+    /// later with `load_state`. Effects without state write nothing.
+    fn save_state(&self, _writer: &mut StateWriter) {}
+
+    /// Restore state written by `save_state` of an effect with the same settings.
+    fn load_state(&mut self, _reader: &mut StateReader) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Names accepted by `set_param_by_name`/`get_param_by_name`.
+    fn param_names(&self) -> &'static [&'static str];
+
+    fn set_param_by_name(&mut self, name: &str, value: f32) -> Result<(), Error>;
+
+    fn get_param_by_name(&self, name: &str) -> Option<f32>;
+}
+
+// Called with an effect's index and output block, see `EffectChain::process_tapped`
+type TapCallback<'a> = dyn FnMut(usize, &[&[f32]]) + 'a;
+
+/// Most channels an `EffectChain` runs; its per-block slice lists live on the stack.
+pub const MAX_CHANNELS: usize = 64;
+
+/// Effects run in series. Intermediate blocks live in two scratch buffers sized for
+/// `max_block_size` when the chain is created, so `process` doesn't allocate.
+pub struct EffectChain {
+    effects: Vec<Box<dyn AudioEffect>>,
+    num_channels: usize,
+    max_block_size: usize,
+    scratch: [Vec<Vec<f32>>; 2],
+    // Chain to crossfade to in the next block, and the one it replaced, kept so that the
+    // caller can take the old effects back
+    pending: Option<Box<EffectChain>>,
+    retired: Option<Box<EffectChain>>,
+}
+
+impl EffectChain {
+    /// Panics if `num_channels` is above `MAX_CHANNELS`.
+    pub fn new(num_channels: usize, max_block_size: usize) -> Self {
+        assert!(num_channels <= MAX_CHANNELS, "At most {} channels", MAX_CHANNELS);
+        EffectChain {
+            effects: Vec::new(),
+            num_channels,
+            max_block_size,
+            scratch: [
+                vec![vec![0.0; max_block_size]; num_channels],
+                vec![vec![0.0; max_block_size]; num_channels],
+            ],
+            pending: None,
+            retired: None,
+        }
+    }
+
+    /// Append an effect to the end of the chain. Its channel count must match the chain's.
+    pub fn push(&mut self, effect: Box<dyn AudioEffect>) {
+        assert_eq!(effect.num_channels(), self.num_channels);
+        self.effects.push(effect);
+    }
+
+    pub fn len(&self) -> usize {
+        self.effects.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.effects.is_empty()
+    }
+
+    pub fn effect(&self, index: usize) -> &dyn AudioEffect {
+        self.effects[index].as_ref()
+    }
+
+    pub fn effect_mut(&mut self, index: usize) -> &mut dyn AudioEffect {
+        self.effects[index].as_mut()
+    }
+
+    /// Switch to the effects of `next`, e.g. the same effects in a different order, in
+    /// the middle of a render without a click. The next `process` call runs both chains
+    /// and crossfades linearly from the old output to the new one over its block; after
+    /// that only the new effects run. `next` starts from its own state, so the old chain's
+    /// tails fade out instead of carrying over. `next` is built, and its buffers allocated,
+    /// before it is handed over, and the crossfade mixes into the chain's own scratch
+    /// buffers, so the `process` call that swaps the chains doesn't allocate. This call
+    /// moves `next` to the heap and drops effects retired by an earlier crossfade and not
+    /// taken with `take_retired`, so make it from the thread that sets the chain up.
+    pub fn crossfade_to(&mut self, next: EffectChain) {
+        assert_eq!(next.num_channels, self.num_channels);
+        assert!(next.max_block_size >= self.max_block_size, "Next chain's max block size is too small");
+        self.retired = None;
+        self.pending = Some(Box::new(next));
+    }
+
+    /// Whether a `crossfade_to` is still waiting for the next block.
+    pub fn is_crossfading(&self) -> bool {
+        self.pending.is_some()
+    }
+
+    /// The effects replaced by the last finished crossfade, e.g. to reuse them.
+    pub fn take_retired(&mut self) -> Option<EffectChain> {
+        self.retired.take().map(|chain| *chain)
+    }
+
+    /// Like `process`, but also calls `on_tap(index, channels)` after each effect with its
+    /// output, one slice of the block's length per channel, e.g. to write per-effect stems.
+    pub fn process_tapped(&mut self, input: &[&[f32]], output: &mut [&mut [f32]], mut on_tap: impl FnMut(usize, &[&[f32]])) {
+        assert!(self.pending.is_none(), "process_tapped doesn't crossfade; finish the crossfade with process first");
+        assert_eq!(input.len(), self.num_channels);
+        assert_eq!(output.len(), self.num_channels);
+        let block_size = input.first().map_or(0, |channel| channel.len());
+        assert!(block_size <= self.max_block_size, "Block is larger than the chain's max block size");
+        self.process_effects(input, output, block_size, Some(&mut on_tap));
+    }
+
+    fn process_effects(
+        &mut self,
+        input: &[&[f32]],
+        output: &mut [&mut [f32]],
+        block_size: usize,
+        mut on_tap: Option<&mut TapCallback>,
+    ) {
+        for (scratch, channel) in self.scratch[0].iter_mut().zip(input) {
+            scratch[..block_size].copy_from_slice(channel);
+        }
+        // Each effect reads from one scratch buffer and writes to the other
+        let num_channels = self.num_channels;
+        for (index, effect) in self.effects.iter_mut().enumerate() {
+            let [front, back] = &mut self.scratch;
+            effect.process(&block_slices(front, block_size)[..num_channels], &mut block_slices_mut(back, block_size)[..num_channels]);
+            if let Some(on_tap) = on_tap.as_mut() {
+                on_tap(index, &block_slices(back, block_size)[..num_channels]);
+            }
+            self.scratch.swap(0, 1);
+        }
+        for (channel, scratch) in output.iter_mut().zip(&self.scratch[0]) {
+            channel[..block_size].copy_from_slice(&scratch[..block_size]);
+        }
+    }
+}
+
+// The first `block_size` samples of each channel, padded with empty slices
+fn block_slices(channels: &[Vec<f32>], block_size: usize) -> [&[f32]; MAX_CHANNELS] {
+    std::array::from_fn(|channel| channels.get(channel).map_or(&[][..], |v| &v[..block_size]))
+}
+
+fn block_slices_mut(channels: &mut [Vec<f32>], block_size: usize) -> [&mut [f32]; MAX_CHANNELS] {
+    let mut channels = channels.iter_mut();
+    std::array::from_fn(|_| channels.next().map_or(Default::default(), |v| &mut v[..block_size]))
+}
+
+impl AudioEffect for EffectChain {
+    fn process(&mut self, input: &[&[f32]], output: &mut [&mut [f32]]) {
+        assert_eq!(input.len(), self.num_channels);
+        assert_eq!(output.len(), self.num_channels);
+        let block_size = input.first().map_or(0, |channel| channel.len());
+        assert!(block_size <= self.max_block_size, "Block is larger than the chain's max block size");
+
+        self.process_effects(input, output, block_size, None);
+        let Some(mut next) = self.pending.take() else {
+            return;
+        };
+        // The old output is in `output`; run the new chain into our free scratch buffer
+        next.process_effects(input, &mut block_slices_mut(&mut self.scratch[1], block_size)[..self.num_channels], block_size, None);
+        for (channel, next_channel) in output.iter_mut().zip(&self.scratch[1]) {
+            for (n, (old, new)) in channel[..block_size].iter_mut().zip(next_channel).enumerate() {
+                let fade = (n + 1) as f32 / block_size as f32;
+                *old += fade * (new - *old);
+            }
+        }
+        std::mem::swap(&mut self.effects, &mut next.effects);
+        self.retired = Some(next);
+    }
+
+    fn reset(&mut self) {
+        self.effects.iter_mut().for_each(|effect| effect.reset());
+    }
+
+    fn num_channels(&self) -> usize {
+        self.num_channels
+    }
+
+    fn latency_samples(&self) -> usize {
+        self.effects.iter().map(|effect| effect.latency_samples()).sum()
+    }
+
+    // The effect count goes first so a state saved from a different chain is rejected
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.put_u64(self.effects.len() as u64);
+        self.effects.iter().for_each(|effect| effect.save_state(writer));
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), Error> {
+        let len = reader.u64()?;
+        if len != self.effects.len() as u64 {
+            return Err(state::state_error(format!("saved from {} effects, loading into {}", len, self.effects.len())));
+        }
+        self.effects.iter_mut().try_for_each(|effect| effect.load_state(reader))
+    }
+
+    // Chain-level parameters would need per-effect addressing; use effect_mut() instead.
+    fn param_names(&self) -> &'static [&'static str] {
+        &[]
+    }
+
+    fn set_param_by_name(&mut self, name: &str, _value: f32) -> Result<(), Error> {
+        Err(Error::UnknownParam { name: name.to_string() })
+    }
+
+    fn get_param_by_name(&self, _name: &str) -> Option<f32> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::comb_filter::{CombFilter, FilterType};
+    use crate::test_alloc::count_allocations;
+
+    #[test]
+    fn test_effect_chain_matches_sequential_processing() {
+        let make_filters = || {
+            (
+                CombFilter::new(FilterType::FIR, 1.0, 100.0, 1, 0.5, 0.03).expect("Failed to create CombFilter"),
+                CombFilter::new(FilterType::IIR, 1.0, 100.0, 1, 0.7, 0.05).expect("Failed to create CombFilter"),
+            )
+        };
+        let input = [(0..64).map(|i| if i % 9 == 0 { 1.0 } else { 0.0 }).collect::<Vec<f32>>()];
+
+        // Run the two filters one after the other by hand
+        let (mut first, mut second) = make_filters();
+        let mut intermediate = vec![vec![0.0; 64]; 1];
+        let mut expected = vec![vec![0.0; 64]; 1];
+        first.process(&input.iter().map(|x| &x[..]).collect::<Vec<_>>(), &mut intermediate.iter_mut().map(|x| &mut x[..]).collect::<Vec<_>>());
+        second.process(&intermediate.iter().map(|x| &x[..]).collect::<Vec<_>>(), &mut expected.iter_mut().map(|x| &mut x[..]).collect::<Vec<_>>());
+
+        // ... and through a chain
+        let (first, second) = make_filters();
+        let mut chain = EffectChain::new(1, 64);
+        chain.push(Box::new(first));
+        chain.push(Box::new(second));
+        let mut output = vec![vec![0.0; 64]; 1];
+        chain.process(&input.iter().map(|x| &x[..]).collect::<Vec<_>>(), &mut output.iter_mut().map(|x| &mut x[..]).collect::<Vec<_>>());
+
+        assert_eq!(output, expected, "Effect chain test failed: output differs from processing the filters in sequence.");
+        assert_eq!(chain.effect(1).get_param_by_name("gain"), Some(0.7));
+    }
+
+    #[test]
+    fn test_chain_crossfades_to_new_order() {
+        // The same two filters, in either order
+        let make_chain = |iir_first: bool| {
+            let fir = CombFilter::new(FilterType::FIR, 0.1, 1000.0, 1, 0.5, 0.03).expect("Failed to create CombFilter");
+            let iir = CombFilter::new(FilterType::IIR, 0.1, 1000.0, 1, 0.7, 0.05).expect("Failed to create CombFilter");
+            let mut chain = EffectChain::new(1, 64);
+            let (first, second): (Box<dyn AudioEffect>, Box<dyn AudioEffect>) =
+                if iir_first { (Box::new(iir), Box::new(fir)) } else { (Box::new(fir), Box::new(iir)) };
+            chain.push(first);
+            chain.push(second);
+            chain
+        };
+        let blocks: Vec<Vec<f32>> = (0..3).map(|b| (0..64).map(|n| ((b * 64 + n) as f32 * 0.37).sin()).collect()).collect();
+        let process = |chain: &mut EffectChain, block: &[f32]| {
+            let mut output = vec![0.0; 64];
+            chain.process(&[block], &mut [&mut output]);
+            output
+        };
+
+        // References: the old order throughout, and the new order starting at the second block
+        let mut old = make_chain(false);
+        let old_output: Vec<Vec<f32>> = blocks.iter().map(|block| process(&mut old, block)).collect();
+        let mut new = make_chain(true);
+        let new_output: Vec<Vec<f32>> = blocks[1..].iter().map(|block| process(&mut new, block)).collect();
+
+        let mut chain = make_chain(false);
+        assert_eq!(process(&mut chain, &blocks[0]), old_output[0]);
+        chain.crossfade_to(make_chain(true));
+        assert!(chain.is_crossfading());
+        let faded = process(&mut chain, &blocks[1]);
+        for (n, &y) in faded.iter().enumerate() {
+            let fade = (n + 1) as f32 / 64.0;
+            let expected = old_output[1][n] + fade * (new_output[0][n] - old_output[1][n]);
+            assert!((y - expected).abs() < 1e-6, "Chain crossfade test failed: sample {} is {}, expected {}.", n, y, expected);
+        }
+        assert!(!chain.is_crossfading());
+        assert_eq!(chain.take_retired().map(|retired| retired.len()), Some(2));
+        assert_eq!(process(&mut chain, &blocks[2]), new_output[1]);
+    }
+
+    #[test]
+    fn test_chain_crossfade_is_allocation_free_and_continuous() {
+        let make_chain = |delay_secs: f32| {
+            let mut chain = EffectChain::new(2, 64);
+            let fir = CombFilter::new(FilterType::FIR, 0.1, 1000.0, 2, 0.5, delay_secs).expect("Failed to create CombFilter");
+            let iir = CombFilter::new(FilterType::IIR, 0.1, 1000.0, 2, 0.7, 0.05).expect("Failed to create CombFilter");
+            chain.push(Box::new(fir));
+            chain.push(Box::new(iir));
+            chain
+        };
+        let signal: Vec<f32> = (0..64 * 4).map(|n| (n as f32 * 0.05).sin()).collect();
+        let run = |chain: &mut EffectChain, crossfade_at: Option<usize>| {
+            let mut output = vec![0.0; signal.len()];
+            let mut left = vec![0.0; 64];
+            let mut right = vec![0.0; 64];
+            for (b, block) in signal.chunks(64).enumerate() {
+                if crossfade_at == Some(b) {
+                    chain.crossfade_to(make_chain(0.08));
+                }
+                let allocations = count_allocations(|| chain.process(&[block, block], &mut [&mut left, &mut right]));
+                assert_eq!(allocations, 0, "Chain allocation test failed: block {} allocated.", b);
+                output[b * 64..(b + 1) * 64].copy_from_slice(&left);
+            }
+            output
+        };
+        let max_step = |x: &[f32]| x.windows(2).map(|w| (w[1] - w[0]).abs()).fold(0.0, f32::max);
+
+        let old = run(&mut make_chain(0.03), None);
+        let new = run(&mut make_chain(0.08), None);
+        let faded = run(&mut make_chain(0.03), Some(2));
+
+        // Fading over a block adds at most 1/64 of the gap between the chains to any step
+        let max_gap = old.iter().zip(&new).map(|(a, b)| (a - b).abs()).fold(0.0, f32::max);
+        let limit = max_step(&old).max(max_step(&new)) + max_gap / 64.0 + 1e-6;
+        assert!(max_step(&faded) <= limit, "Chain crossfade test failed: step {} exceeds {}.", max_step(&faded), limit);
+        assert_eq!(faded[..128], old[..128]);
+    }
+}
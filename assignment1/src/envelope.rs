@@ -0,0 +1,46 @@
+use crate::error::Error;
+
+/// Piecewise-linear curve through `(time_secs, value)` breakpoints. Before the first
+/// breakpoint and after the last one the curve holds that breakpoint's value.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Envelope {
+    points: Vec<(f32, f32)>,
+}
+
+impl Envelope {
+    /// Breakpoints must be in time order; two points at the same time make a step.
+    pub fn new(points: Vec<(f32, f32)>) -> Result<Self, Error> {
+        // An envelope needs at least one breakpoint
+        if points.is_empty() {
+            return Err(Error::InvalidValue { param: "envelope points", value: 0.0 });
+        }
+        let out_of_order = points.windows(2).find(|w| w[1].0 < w[0].0).map(|w| w[1].0);
+        let not_a_number = points.iter().find(|p| p.0.is_nan()).map(|p| p.0);
+        if let Some(time) = out_of_order.or(not_a_number) {
+            return Err(Error::InvalidValue { param: "envelope time", value: time });
+        }
+        Ok(Envelope { points })
+    }
+
+    pub fn constant(value: f32) -> Self {
+        Envelope { points: vec![(0.0, value)] }
+    }
+
+    pub fn points(&self) -> &[(f32, f32)] {
+        &self.points
+    }
+
+    pub fn value_at(&self, time_secs: f32) -> f32 {
+        // Index of the first breakpoint after `time_secs`
+        let next = self.points.partition_point(|&(t, _)| t <= time_secs);
+        if next == 0 {
+            return self.points[0].1;
+        }
+        if next == self.points.len() {
+            return self.points[next - 1].1;
+        }
+        let (t0, v0) = self.points[next - 1];
+        let (t1, v1) = self.points[next];
+        v0 + (v1 - v0) * (time_secs - t0) / (t1 - t0)
+    }
+}
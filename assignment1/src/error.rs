@@ -0,0 +1,99 @@
+use std::fmt;
+use std::path::PathBuf;
+
+use crate::comb_filter;
+use crate::memory;
+
+/// Error type shared by the library's processors, effect chains and renderer.
+#[derive(Debug)]
+pub enum Error {
+    /// A parameter value outside the range the processor accepts.
+    InvalidValue { param: &'static str, value: f32 },
+    /// A parameter name the processor doesn't have.
+    UnknownParam { name: String },
+    /// Reading or writing a WAV file failed.
+    Wav(hound::Error),
+    /// Reading or writing a file other than a WAV file failed.
+    Io(std::io::Error),
+    /// A preset file that doesn't follow the preset format.
+    Preset { line: usize, message: String },
+    /// An input file whose sample rate or channel count doesn't match the rest of the render.
+    IncompatibleInput { path: PathBuf, sample_rate: u32, channels: u16 },
+    /// A render that would allocate more memory than its limit allows.
+    MemoryLimit { needed: usize, limit: usize },
+    /// Saved processor state that doesn't fit the effects it is loaded into.
+    State { message: String },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::InvalidValue { param, value } => write!(f, "invalid value {} for parameter '{}'", value, param),
+            Error::UnknownParam { name } => write!(f, "unknown parameter '{}'", name),
+            Error::Wav(err) => write!(f, "WAV file error: {}", err),
+            Error::Io(err) => write!(f, "I/O error: {}", err),
+            Error::Preset { line, message } => write!(f, "preset line {}: {}", line, message),
+            Error::IncompatibleInput { path, sample_rate, channels } => write!(
+                f,
+                "{} has {} Hz and {} channels, which doesn't match the render",
+                path.display(),
+                sample_rate,
+                channels
+            ),
+            Error::MemoryLimit { needed, limit } => write!(
+                f,
+                "render needs about {} but the memory limit is {}",
+                memory::format_bytes(*needed),
+                memory::format_bytes(*limit)
+            ),
+            Error::State { message } => write!(f, "processor state: {}", message),
+        }
+    }
+}
+
+impl Error {
+    /// Short stable identifier of the kind of error, for tools that match on errors
+    /// rather than on the (possibly reworded) message.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Error::InvalidValue { .. } => "invalid_value",
+            Error::UnknownParam { .. } => "unknown_param",
+            Error::Wav(_) => "wav",
+            Error::Io(_) => "io",
+            Error::Preset { .. } => "preset",
+            Error::IncompatibleInput { .. } => "incompatible_input",
+            Error::MemoryLimit { .. } => "memory_limit",
+            Error::State { .. } => "state",
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Wav(err) => Some(err),
+            Error::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<hound::Error> for Error {
+    fn from(err: hound::Error) -> Self {
+        Error::Wav(err)
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(err: std::io::Error) -> Self {
+        Error::Io(err)
+    }
+}
+
+impl From<comb_filter::Error> for Error {
+    fn from(err: comb_filter::Error) -> Self {
+        match err {
+            comb_filter::Error::InvalidValue { param, value } => Error::InvalidValue { param: param.name(), value },
+        }
+    }
+}
@@ -0,0 +1,189 @@
+use crate::effect::AudioEffect;
+use crate::error::Error;
+use crate::state::{self, StateReader, StateWriter};
+use crate::units::db_to_linear;
+
+// Time constant of the envelope follower that drives the signal-dependent noise
+const ENVELOPE_SECS: f32 = 0.01;
+// Corner of the lowpass that takes the edge off the white noise
+const TONE_HZ: f32 = 8000.0;
+
+/// Tape-style noise layer added to the signal. Part of the noise is a constant hiss
+/// floor; the rest (asperity noise) follows the signal's envelope, so it rises and
+/// falls with the music. The noise is seeded: the same seed gives the same noise, and
+/// `reset` restarts it from the seed.
+pub struct Hiss {
+    sample_rate_hz: f32,
+    num_channels: usize,
+    seed: u64,
+    level_db: f32,
+    tracking: f32,
+    level: f32,
+    envelope_coeff: f32,
+    tone_coeff: f32,
+    // Per channel: noise generator state, envelope follower, noise lowpass
+    rng: Vec<u64>,
+    envelope: Vec<f32>,
+    tone: Vec<f32>,
+}
+
+impl Hiss {
+    /// `level_db` is the noise level at full-scale signal (or always, with no
+    /// tracking); `tracking` (0 to 1) is the share of the noise that follows the
+    /// signal envelope. Allocates the per-channel state; not real-time safe.
+    pub fn new(sample_rate_hz: f32, num_channels: usize, level_db: f32, tracking: f32, seed: u64) -> Result<Self, Error> {
+        let mut hiss = Hiss {
+            sample_rate_hz,
+            num_channels,
+            seed,
+            level_db: 0.0,
+            tracking: 0.0,
+            level: 0.0,
+            envelope_coeff: (-1.0 / (ENVELOPE_SECS * sample_rate_hz)).exp(),
+            tone_coeff: (-std::f32::consts::TAU * TONE_HZ.min(0.45 * sample_rate_hz) / sample_rate_hz).exp(),
+            rng: vec![0; num_channels],
+            envelope: vec![0.0; num_channels],
+            tone: vec![0.0; num_channels],
+        };
+        hiss.set_level_db(level_db)?;
+        hiss.set_tracking(tracking)?;
+        hiss.reset();
+        Ok(hiss)
+    }
+
+    pub fn set_level_db(&mut self, level_db: f32) -> Result<(), Error> {
+        if level_db > 0.0 || level_db.is_nan() {
+            return Err(Error::InvalidValue { param: "level_db", value: level_db });
+        }
+        self.level_db = level_db;
+        self.level = db_to_linear(level_db);
+        Ok(())
+    }
+
+    pub fn set_tracking(&mut self, tracking: f32) -> Result<(), Error> {
+        if !(0.0..=1.0).contains(&tracking) {
+            return Err(Error::InvalidValue { param: "tracking", value: tracking });
+        }
+        self.tracking = tracking;
+        Ok(())
+    }
+
+    pub fn level_db(&self) -> f32 {
+        self.level_db
+    }
+
+    pub fn tracking(&self) -> f32 {
+        self.tracking
+    }
+
+    pub fn sample_rate(&self) -> f32 {
+        self.sample_rate_hz
+    }
+}
+
+// xorshift64 step, returning white noise in -1..1
+pub(crate) fn next_noise(state: &mut u64) -> f32 {
+    *state ^= *state << 13;
+    *state ^= *state >> 7;
+    *state ^= *state << 17;
+    (*state >> 40) as f32 / (1u64 << 23) as f32 - 1.0
+}
+
+impl AudioEffect for Hiss {
+    fn process(&mut self, input: &[&[f32]], output: &mut [&mut [f32]]) {
+        assert_eq!(input.len(), self.num_channels);
+        assert_eq!(output.len(), self.num_channels);
+        for channel in 0..self.num_channels {
+            for (out, &x) in output[channel].iter_mut().zip(input[channel]) {
+                let envelope = &mut self.envelope[channel];
+                *envelope = (1.0 - self.envelope_coeff) * x.abs() + self.envelope_coeff * *envelope;
+                let tone = &mut self.tone[channel];
+                *tone = (1.0 - self.tone_coeff) * next_noise(&mut self.rng[channel]) + self.tone_coeff * *tone;
+                let amount = self.level * ((1.0 - self.tracking) + self.tracking * *envelope);
+                *out = x + amount * *tone;
+            }
+        }
+    }
+
+    fn reset(&mut self) {
+        for (channel, state) in self.rng.iter_mut().enumerate() {
+            // xorshift must not start at zero
+            *state = (self.seed ^ (channel as u64 + 1).wrapping_mul(0x9E37_79B9_7F4A_7C15)).max(1);
+        }
+        self.envelope.iter_mut().for_each(|x| *x = 0.0);
+        self.tone.iter_mut().for_each(|x| *x = 0.0);
+    }
+
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.put_u64(self.rng.len() as u64);
+        self.rng.iter().for_each(|&state| writer.put_u64(state));
+        writer.put_f32s(&self.envelope);
+        writer.put_f32s(&self.tone);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), Error> {
+        let len = reader.u64()?;
+        if len != self.rng.len() as u64 {
+            return Err(state::state_error(format!("expected {} noise generators, found {}", self.rng.len(), len)));
+        }
+        for rng in self.rng.iter_mut() {
+            *rng = reader.u64()?;
+        }
+        reader.f32s_into(&mut self.envelope)?;
+        reader.f32s_into(&mut self.tone)
+    }
+
+    fn num_channels(&self) -> usize {
+        self.num_channels
+    }
+
+    fn param_names(&self) -> &'static [&'static str] {
+        &["level_db", "tracking"]
+    }
+
+    fn set_param_by_name(&mut self, name: &str, value: f32) -> Result<(), Error> {
+        match name {
+            "level_db" => self.set_level_db(value),
+            "tracking" => self.set_tracking(value),
+            _ => Err(Error::UnknownParam { name: name.to_string() }),
+        }
+    }
+
+    fn get_param_by_name(&self, name: &str) -> Option<f32> {
+        match name {
+            "level_db" => Some(self.level_db),
+            "tracking" => Some(self.tracking),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_kit;
+
+    #[test]
+    fn test_hiss_is_seeded_and_tracks_signal() {
+        // Noise is never silent, so only the checks that don't expect silence apply
+        let make_hiss = |num_channels| Box::new(Hiss::new(44100.0, num_channels, -40.0, 0.5, 7).unwrap()) as Box<dyn AudioEffect>;
+        test_kit::check_block_size_invariance("hiss", &make_hiss, 2, &[1, 64, 1000]);
+        test_kit::check_reset_equivalence("hiss", &make_hiss, 2);
+        test_kit::check_non_finite_recovery("hiss", &make_hiss, 2);
+
+        let render_silence = |tracking: f32, seed: u64| {
+            let mut hiss = Hiss::new(44100.0, 1, -40.0, tracking, seed).expect("Failed to create Hiss");
+            let silence = vec![0.0; 4096];
+            let mut output = vec![0.0; 4096];
+            hiss.process(&[&silence], &mut [&mut output]);
+            output
+        };
+        assert_eq!(render_silence(0.0, 1), render_silence(0.0, 1));
+        assert_ne!(render_silence(0.0, 1), render_silence(0.0, 2));
+        // Fully tracking noise disappears with the signal; a constant floor stays below its level
+        assert!(render_silence(1.0, 1).iter().all(|&x| x == 0.0), "Hiss test failed: tracking noise on silence.");
+        let floor = render_silence(0.0, 1);
+        let peak = floor.iter().fold(0.0_f32, |peak, x| peak.max(x.abs()));
+        assert!(peak > 0.0 && peak <= 0.01, "Hiss test failed: noise floor peak {} outside (0, -40 dB].", peak);
+    }
+}
@@ -0,0 +1,51 @@
+//! Comb filter DSP library behind the MUSI-6106 assignment executable.
+//!
+//! [`CombFilter`] is the processor; it implements [`AudioEffect`] so several filters
+//! can run in series in an [`EffectChain`]. [`render::run`] is the whole file-to-file
+//! pipeline the binary uses, returning a [`RenderReport`] instead of printing;
+//! [`arrangement::render`] does the same for several files placed on tracks.
+//! [`test_kit`] holds the checks every effect should pass, for use in downstream tests.
+//! `examples/custom_effect.rs` implements a new effect outside the crate and runs it
+//! through the test kit, a [`preset`] and a chain.
+//!
+//! # Real-time safety
+//! Constructors (`CombFilter::new`, `CombFilterBuilder::build`, `EffectChain::new`)
+//! allocate everything up front. After that, `CombFilter::process`, `reset`,
+//! `set_param` and `get_param` do not allocate or block and are safe to call from an
+//! audio callback. `set_metering` and `set_sample_rate` allocate and registering
+//! callbacks boxes them, so call those during setup. `EffectChain::process` doesn't
+//! allocate either, including the block that crossfades to a chain handed over with
+//! `crossfade_to`; build that chain and call `crossfade_to` outside the callback.
+
+pub mod analysis;
+pub mod arrangement;
+pub mod checksum;
+pub mod comb_filter;
+pub mod crosstalk;
+pub mod diagnostic;
+pub mod drift;
+pub mod effect;
+pub mod envelope;
+pub mod error;
+pub mod hiss;
+pub mod memory;
+pub mod meter;
+pub mod preset;
+pub mod quality;
+pub mod render;
+pub mod sample_format;
+pub mod smoother;
+pub mod state;
+pub mod test_kit;
+#[cfg(test)]
+mod test_alloc;
+#[cfg(test)]
+mod test_wav;
+pub mod units;
+pub mod viz;
+pub mod waveform;
+
+pub use comb_filter::{CombFilter, CombFilterBuilder, FilterParam, FilterType, LinkMode};
+pub use effect::{AudioEffect, EffectChain};
+pub use error::Error;
+pub use render::{Automation, FilterSettings, RenderConfig, RenderReport};
@@ -1,18 +1,47 @@
 
-use std::{env, fs::File, io::Write, path::Path};
-use hound::{WavReader, WavWriter, WavSpec, SampleFormat};
+use std::{env, path::{Path, PathBuf}, sync::OnceLock};
 
-mod comb_filter;
-use comb_filter::{CombFilter, FilterType};
+use ase::comb_filter::{CombFilter, FilterParam, FilterType};
+use ase::diagnostic::Diagnostic;
+use ase::drift::Drift;
+use ase::envelope::Envelope;
+use ase::effect::EffectChain;
+use ase::render::{self, Automation, FilterSettings, RenderConfig};
+use ase::Error;
+use ase::{analysis, checksum, memory, preset, quality, sample_format, units, viz, waveform};
 
 fn show_info() {
     eprintln!("MUSI-6106 Assignment Executable");
     eprintln!("(c) 2024 Stephen Garrett & Ian Clester");
 }
 
-fn main() {
-    show_info();
+// Whether errors and warnings are printed as JSON, set from --error-format before anything can fail
+static JSON_ERRORS: OnceLock<bool> = OnceLock::new();
 
+// Print an error or warning to stderr in the format chosen with --error-format
+fn emit(diagnostic: &Diagnostic) {
+    if JSON_ERRORS.get() == Some(&true) {
+        eprintln!("{}", diagnostic.to_json());
+    } else {
+        eprintln!("{}", diagnostic);
+    }
+}
+
+fn fail(diagnostic: Diagnostic, exit_code: i32) -> ! {
+    emit(&diagnostic);
+    std::process::exit(exit_code);
+}
+
+fn invalid_option(option: &str, message: String, expected: &str) -> ! {
+    fail(Diagnostic::error("invalid_option", message).with_param(option).with_expected(expected), 1)
+}
+
+// The value, or exit through `fail` with `context` in front of the error message
+fn or_fail<T, E: Into<Error>>(result: Result<T, E>, context: &str) -> T {
+    result.unwrap_or_else(|e| fail(Diagnostic::from_error(context, &e.into()), 1))
+}
+
+fn main() {
     // Parse command line arguments
     let args: Vec<String> = env::args().collect();
     if args.len() == 0 {
@@ -24,89 +53,287 @@ fn main() {
         test_buffer_length_greater_than_input_length();
         std::process::exit(1);
     }
+    let error_format = args.iter().position(|arg| arg == "--error-format").map(|i| args.get(i + 1).map(String::as_str).unwrap_or(""));
+    match error_format {
+        None | Some("text") => JSON_ERRORS.set(false).unwrap(),
+        Some("json") => JSON_ERRORS.set(true).unwrap(),
+        Some(other) => invalid_option("--error-format", format!("Invalid error format: {:?}", other), "text or json"),
+    }
+    // With JSON errors stderr carries nothing but diagnostics
+    if JSON_ERRORS.get() == Some(&false) {
+        show_info();
+    }
+    if args.get(1).map(String::as_str) == Some("--analyze") {
+        run_analysis(&args);
+        return;
+    }
     if args.len() < 4 {
-        eprintln!("Usage: {} <input wave filename> <output wave filename> <effect-parameters>", args[0]);
+        if JSON_ERRORS.get() == Some(&true) {
+            fail(Diagnostic::error("usage", "Missing arguments").with_expected("<input> <output> <effect-parameters>|--preset <file> [options]"), 1);
+        }
+        eprintln!("Usage: {} <input wave filename> <output wave filename> <effect-parameters>[+<effect-parameters>...] [--viz] [--emit-hash] [--verify-hash <hash>] [--out-format 16|24|32|float] [--save-preset <file>] [--automate <filter>:<gain|delay>:<secs>=<value>,...]... [--drift <seed>[,<amount>]] [--max-memory <bytes>[K|M|G]] [--permissive] [--load-state <file>] [--save-state <file>] [--stems] [--explain <samples>] [--quality-report [--quality-limits <name=value,...>]] [--error-format text|json]", args[0]);
+        eprintln!("       {} <input wave filename> <output wave filename> --preset <file> [options]", args[0]);
+        eprintln!("       {} --analyze impulse|sweep|dc <output wave filename> <effect-parameters>[+<effect-parameters>...]", args[0]);
         std::process::exit(1);
     }
 
-    // Open the input wave file
-    let input_path = Path::new(&args[1]);
-    let mut reader = WavReader::open(input_path).expect("Failed to open WAV file");
-
-    // Prepare the output WAV file
-    let spec = reader.spec();
-    let output_path = Path::new(&args[2]);
-    let mut writer = WavWriter::create(output_path, spec).expect("Failed to create WAV file");
+    // The effect parameters can be replaced by --preset, in which case options start right away
+    let options_start = if args[3].starts_with("--") { 3 } else { 4 };
+    let options = &args[options_start..];
+    let option_value = |name: &str| {
+        options.iter().position(|arg| arg == name).map(|i| options.get(i + 1).map(String::as_str).unwrap_or(""))
+    };
 
-    let filter_params: Vec<&str> = args[3].split(',').collect();
-    if filter_params.len() != 5 {
-        eprintln!("Invalid number of effect parameters. Expected 5, found {}", filter_params.len());
-        std::process::exit(1);
-    }
-    let filter_type = match filter_params[0] {
-        "FIR" => FilterType::FIR,
-        "IIR" => FilterType::IIR,
-        _ => {
-            eprintln!("Invalid filter type: {}", filter_params[0]);
-            std::process::exit(1);
+    let filters: Vec<FilterSettings> = match option_value("--preset") {
+        Some(path) => {
+            if options_start == 4 {
+                fail(Diagnostic::error("usage", "Give either effect parameters or --preset, not both").with_param("--preset"), 1);
+            }
+            preset::load(Path::new(path))
+                .unwrap_or_else(|e| fail(Diagnostic::from_error(&format!("Failed to load preset {}", path), &e).with_param("--preset"), 1))
+        }
+        // Several filters can be chained by joining their parameter groups with '+'
+        None if options_start == 4 => args[3].split('+').map(parse_filter_settings).collect(),
+        None => {
+            fail(Diagnostic::error("usage", "Missing effect parameters (or --preset <file>)"), 1);
         }
     };
+    if let Some(path) = option_value("--save-preset") {
+        if let Err(e) = preset::save(Path::new(path), &filters) {
+            fail(Diagnostic::from_error(&format!("Failed to save preset {}", path), &e).with_param("--save-preset"), 1);
+        }
+    }
 
-    let max_delay_secs = filter_params[1].parse::<f32>().expect("Invalid max delay seconds");
-    let sample_rate_hz = filter_params[2].parse::<f32>().expect("Invalid sample rate Hz");
-    let gain = filter_params[3].parse::<f32>().expect("Invalid gain");
-    let delay_secs = filter_params[4].parse::<f32>().expect("Invalid delay samples");
-   
-
-
-    // Process audio in blocks
-    let block_size_per_channel = 1024;
-    let num_samples = reader.len() as usize;
-    // let num_blocks = num_samples / block_size_per_channel;
-    let channels = spec.channels as usize;
-    let mut comb_filter = CombFilter::new(filter_type, max_delay_secs, sample_rate_hz, channels, gain, delay_secs).expect("Failed to create CombFilter");
-    // Initialize buffers for processing
-    let mut input_blocks: Vec<Vec<f32>> = vec![vec![0.0; block_size_per_channel]; channels];
-    let mut output_blocks: Vec<Vec<f32>> = vec![vec![0.0; block_size_per_channel]; channels];
+    let output_format = option_value("--out-format").map(|value| {
+        sample_format::OutputFormat::parse(value)
+            .unwrap_or_else(|| invalid_option("--out-format", format!("Invalid output format: {:?}", value), "16, 24, 32 or float"))
+    });
+
+    // Each --automate gives one parameter's breakpoints, e.g. 0:delay:0=0.001,5=0.01
+    let automation: Vec<Automation> = options
+        .iter()
+        .enumerate()
+        .filter(|(_, arg)| *arg == "--automate")
+        .map(|(i, _)| parse_automation(options.get(i + 1).map(String::as_str).unwrap_or("")))
+        .collect();
+    let drift = option_value("--drift").map(|value| {
+        Drift::parse(value).unwrap_or_else(|| invalid_option("--drift", format!("Invalid drift: {:?}", value), "<seed>[,<amount below 1>]"))
+    });
+    let max_memory_bytes = option_value("--max-memory").map(|value| {
+        memory::parse_size(value).unwrap_or_else(|| {
+            invalid_option("--max-memory", format!("Invalid size for --max-memory: {:?}", value), "bytes with an optional K, M or G suffix")
+        })
+    });
+
+    let config = RenderConfig {
+        output_format,
+        automation,
+        drift,
+        max_memory_bytes,
+        load_state_path: option_value("--load-state").map(PathBuf::from),
+        save_state_path: option_value("--save-state").map(PathBuf::from),
+        stems: options.iter().any(|arg| arg == "--stems"),
+        permissive: options.iter().any(|arg| arg == "--permissive"),
+        ..RenderConfig::new(&args[1], &args[2], filters)
+    };
+    let show_viz = options.iter().any(|arg| arg == "--viz");
+    let explain_samples = option_value("--explain").map(|value| {
+        value
+            .parse::<usize>()
+            .unwrap_or_else(|_| invalid_option("--explain", format!("Invalid sample count for --explain: {:?}", value), "a whole number of samples"))
+    });
+    let quality_thresholds = options.iter().any(|arg| arg == "--quality-report").then(|| {
+        let mut thresholds = quality::QualityThresholds::default();
+        if let Some(spec) = option_value("--quality-limits") {
+            thresholds
+                .parse_overrides(spec)
+                .unwrap_or_else(|e| fail(Diagnostic::from_error("Invalid --quality-limits", &e).with_param("--quality-limits"), 1));
+        }
+        thresholds
+    });
+    let emit_hash = options.iter().any(|arg| arg == "--emit-hash");
+    let expected_hash = option_value("--verify-hash").map(|value| {
+        checksum::from_hex(value)
+            .unwrap_or_else(|| invalid_option("--verify-hash", format!("Invalid hash for --verify-hash: {:?}", value), "16 hex digits"))
+    });
+
+    let report = match render::run(&config) {
+        Ok(report) => report,
+        Err(e) => fail(Diagnostic::from_error("Render failed", &e), 1),
+    };
+    for path in &report.stem_paths {
+        eprintln!("Wrote stem {}", path.display());
+    }
+    if max_memory_bytes.is_some() {
+        eprintln!("Memory: {}", report.memory);
+    }
+    for offset in &report.drift_offsets {
+        eprintln!("Drift: filter {} {} {} -> {}", offset.filter_index, offset.param.name(), offset.nominal, offset.drifted);
+    }
+    for (index, &num_clamped) in report.clamped_params.iter().enumerate().filter(|(_, &n)| n > 0) {
+        emit(&Diagnostic::warning("clamped_params", format!("Filter {}: {} parameter values clamped", index, num_clamped)));
+    }
+    if report.num_clipped > 0 {
+        emit(&Diagnostic::warning("clipping", format!("{} output samples clipped", report.num_clipped)));
+    }
+    if emit_hash {
+        println!("Output hash: {}", checksum::to_hex(report.output_hash));
+    }
+    if let Some(expected) = expected_hash {
+        if report.output_hash != expected {
+            let message = format!("Hash mismatch: expected {}, got {}", checksum::to_hex(expected), checksum::to_hex(report.output_hash));
+            fail(Diagnostic::error("hash_mismatch", message).with_param("--verify-hash"), 2);
+        }
+        eprintln!("Output hash verified");
+    }
 
-    let mut total_samples_written = 0;
+    if let Some(num_samples) = explain_samples {
+        explain(&config, num_samples);
+    }
 
-    while let Some(samples) = reader.samples::<i16>().take(block_size_per_channel * channels).collect::<Result<Vec<_>, _>>().ok() {
-        if samples.is_empty() {
-            break;
+    if let Some(thresholds) = &quality_thresholds {
+        let quality_report = or_fail(quality::analyze_wav(&config.output_path, thresholds), "Failed to read output WAV file");
+        println!("Quality report:");
+        println!("{}", quality_report);
+        if !quality_report.passed() {
+            std::process::exit(3);
         }
-        let actual_block_size = samples.len() / channels; // Actual number of samples per channel in this block
+    }
 
-        // Clear previous block data
-        for channel_data in &mut input_blocks {
-            channel_data.fill(0.0);
+    if show_viz {
+        const VIZ_WIDTH: usize = 72;
+        println!("Output RMS:");
+        println!("{}", viz::sparkline(&report.block_rms, VIZ_WIDTH));
+        let overviews = or_fail(waveform::WaveformOverview::from_wav(&config.output_path, 256), "Failed to read output WAV file");
+        for (channel, overview) in overviews.iter().enumerate() {
+            println!("Output waveform, channel {}:", channel);
+            for row in viz::waveform_rows(&overview.peaks_for_width(VIZ_WIDTH), 8) {
+                println!("{}", row);
+            }
         }
+    }
+}
 
-        // Convert and separate samples into channels
-        for (i, sample) in samples.iter().enumerate() {
-            let channel_index = i % channels;
-            let sample_index = i / channels;
-            input_blocks[channel_index][sample_index] = *sample as f32 / i16::MAX as f32;
+fn parse_filter_settings(group: &str) -> FilterSettings {
+    let filter_params: Vec<&str> = group.split(',').collect();
+    let invalid = |message: String| Diagnostic::error("invalid_effect_params", message);
+    if filter_params.len() != 5 {
+        let message = format!("Invalid number of effect parameters. Expected 5, found {}", filter_params.len());
+        fail(invalid(message).with_expected("<FIR|IIR>,<max delay secs>,<sample rate Hz>,<gain>,<delay secs>"), 1);
+    }
+    let filter_type = match filter_params[0] {
+        "FIR" => FilterType::FIR,
+        "IIR" => FilterType::IIR,
+        _ => fail(invalid(format!("Invalid filter type: {}", filter_params[0])).with_param("type").with_expected("FIR or IIR"), 1),
+    };
+    let number = |index: usize, param: &str| {
+        filter_params[index]
+            .parse::<f32>()
+            .unwrap_or_else(|_| fail(invalid(format!("Invalid {}: {:?}", param, filter_params[index])).with_param(param).with_expected("a number"), 1))
+    };
+    FilterSettings {
+        filter_type,
+        max_delay_secs: number(1, "max_delay_secs"),
+        sample_rate_hz: number(2, "sample_rate_hz"),
+        gain: number(3, "gain"),
+        delay_secs: number(4, "delay_secs"),
+    }
+}
+
+// Print what every filter in the chain does to each of the first `num_samples` samples of
+// the input's first channel, one table per filter, feeding each filter's output to the next
+fn explain(config: &RenderConfig, num_samples: usize) {
+    let mut reader = or_fail(hound::WavReader::open(&config.input_path), "Failed to open input WAV file");
+    let channels = reader.spec().channels as usize;
+    let sample_rate_hz = reader.spec().sample_rate as f32;
+    let mut signal: Vec<f32> = sample_format::samples_f32(&mut reader)
+        .step_by(channels)
+        .take(num_samples)
+        .map(|sample| or_fail(sample, "Failed to read input sample"))
+        .collect();
+    for (index, settings) in config.filters.iter().enumerate() {
+        let mut filter = or_fail(
+            CombFilter::new(settings.filter_type, settings.max_delay_secs, sample_rate_hz, 1, settings.gain, settings.delay_secs),
+            "Invalid effect parameters",
+        );
+        let mut output = vec![0.0; signal.len()];
+        let trace = filter.process_traced(&[&signal], &mut [&mut output]);
+
+        println!("Filter {} ({:?}, gain {}, delay {} samples):", index, settings.filter_type, settings.gain, units::secs_to_frac_samples(settings.delay_secs, sample_rate_hz));
+        println!("{:>6} {:>10} {:>9} {:>15} {:>10} {:>6} {:>10} {:>6} {:>10}", "n", "x[n]", "delay", "weights", "delayed", "gain", "y[n]", "write", "written");
+        for (n, step) in trace[0].iter().take(num_samples).enumerate() {
+            println!(
+                "{:>6} {:>10.6} {:>9.3} {:>7.3}/{:<7.3} {:>10.6} {:>6.3} {:>10.6} {:>6} {:>10.6}",
+                n, step.input, step.delay_samples, step.weights[0], step.weights[1], step.delayed, step.gain, step.output, step.write_index, step.written
+            );
         }
+        signal = output;
+    }
+}
 
-        // Process each block
-        let input_slices: Vec<&[f32]> = input_blocks.iter().map(|v| v.as_slice()).collect();
-        let mut output_slices: Vec<&mut [f32]> = output_blocks.iter_mut().map(|v| v.as_mut_slice()).collect();
-        comb_filter.process(&input_slices, &mut output_slices);
-
-        // Write processed samples back, interleaving channels
-        for i in 0..actual_block_size {
-            for channel in 0..channels {
-                let sample = output_blocks[channel][i];
-                let sample_i16 = (sample * i16::MAX as f32) as i16;
-                writer.write_sample(sample_i16).expect("Failed to write sample");
-                total_samples_written += 1;
-            }
+// Render a generated test signal through the effect chain instead of an input file and
+// report the output peak and latency
+fn run_analysis(args: &[String]) {
+    if args.len() < 5 {
+        if JSON_ERRORS.get() == Some(&true) {
+            fail(Diagnostic::error("usage", "Missing arguments").with_expected("--analyze impulse|sweep|dc <output> <effect-parameters>"), 1);
         }
+        eprintln!("Usage: {} --analyze impulse|sweep|dc <output wave filename> <effect-parameters>[+<effect-parameters>...]", args[0]);
+        std::process::exit(1);
+    }
+    let signal = analysis::TestSignal::parse(&args[2])
+        .unwrap_or_else(|| invalid_option("--analyze", format!("Invalid test signal: {:?}", args[2]), "impulse, sweep or dc"));
+    let filters: Vec<FilterSettings> = args[4].split('+').map(parse_filter_settings).collect();
+    let sample_rate_hz = filters[0].sample_rate_hz;
+    const BLOCK_SIZE: usize = 1024;
+    let mut chain = EffectChain::new(1, BLOCK_SIZE);
+    for settings in &filters {
+        let filter = or_fail(
+            CombFilter::new(settings.filter_type, settings.max_delay_secs, settings.sample_rate_hz, 1, settings.gain, settings.delay_secs),
+            "Invalid effect parameters",
+        );
+        chain.push(Box::new(filter));
     }
 
-    writer.finalize().expect("Failed to finalize WAV file");
+    // Two seconds of signal, written as a mono float file
+    let num_frames = units::secs_to_samples(2.0, sample_rate_hz);
+    let (output, report) = or_fail(analysis::analyze(&mut chain, signal, sample_rate_hz, num_frames, BLOCK_SIZE), "Analysis failed");
+    let spec = hound::WavSpec { channels: 1, sample_rate: sample_rate_hz as u32, bits_per_sample: 32, sample_format: hound::SampleFormat::Float };
+    let mut writer = or_fail(hound::WavWriter::create(&args[3], spec), "Failed to create output WAV file");
+    output[0].iter().for_each(|&sample| or_fail(writer.write_sample(sample), "Failed to write output sample"));
+    or_fail(writer.finalize(), "Failed to finalize output WAV file");
+
+    println!("Peak level: {:.4} ({:.2} dBFS)", report.peak, units::linear_to_db(report.peak));
+    match report.measured_latency {
+        Some(latency) => println!("Measured latency: {} samples ({:.3} ms)", latency, units::samples_to_ms(latency, sample_rate_hz)),
+        None => println!("Measured latency: n/a for a DC signal"),
+    }
+    println!("Reported latency: {} samples", report.reported_latency);
+}
 
+fn parse_automation(spec: &str) -> Automation {
+    let fields: Vec<&str> = spec.splitn(3, ':').collect();
+    fn invalid(message: String) -> ! {
+        invalid_option("--automate", message, "<filter>:<gain|delay>:<secs>=<value>,...")
+    }
+    if fields.len() != 3 {
+        invalid(format!("Invalid automation {:?}", spec));
+    }
+    let filter_index = fields[0].parse::<usize>().unwrap_or_else(|_| invalid(format!("Invalid automation filter index: {:?}", fields[0])));
+    let param = FilterParam::from_name(fields[1]).unwrap_or_else(|| invalid(format!("Invalid automation parameter: {}", fields[1])));
+    let points = fields[2]
+        .split(',')
+        .map(|point| {
+            let number = |text: &str| text.parse::<f32>().ok();
+            match point.split_once('=').map(|(time, value)| (number(time), number(value))) {
+                Some((Some(time), Some(value))) => (time, value),
+                _ => invalid(format!("Invalid automation breakpoint: {:?}", point)),
+            }
+        })
+        .collect();
+    let envelope = Envelope::new(points)
+        .unwrap_or_else(|e| fail(Diagnostic::from_error("Invalid automation envelope", &e).with_param("--automate"), 1));
+    Automation { filter_index, param, envelope }
 }
 
 fn test_fir_output_zero_on_feedforward_freq() {
@@ -191,4 +418,4 @@ fn test_buffer_length_greater_than_input_length() {
 
     // This call should trigger the panic based on the assertion in your process method
     filter.process(&input_signal.iter().map(|x| &x[..]).collect::<Vec<_>>(), &mut output_signal.iter_mut().map(|x| &mut x[..]).collect::<Vec<_>>());
-}
\ No newline at end of file
+}
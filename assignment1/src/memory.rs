@@ -0,0 +1,153 @@
+use std::fmt;
+
+use crate::comb_filter;
+use crate::error::Error;
+use crate::render::FilterSettings;
+
+// Up-front accounting of what a render will allocate, so a render that would not fit
+// on the machine fails with a clear message before allocating anything. Only the large
+// allocations are counted: delay lines, block buffers and audio read into memory.
+
+const SAMPLE_BYTES: usize = std::mem::size_of::<f32>();
+
+/// Bytes a render allocates, by kind.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct MemoryUsage {
+    /// Effect state such as comb filter delay lines.
+    pub delay_lines: usize,
+    /// Per-block input, output and scratch buffers.
+    pub buffers: usize,
+    /// Input audio held in memory for the whole render (arrangement regions).
+    pub audio: usize,
+}
+
+impl MemoryUsage {
+    pub fn total(&self) -> usize {
+        self.delay_lines + self.buffers + self.audio
+    }
+
+    /// Fails with `Error::MemoryLimit` if the total is above `limit`.
+    pub fn check(&self, limit: Option<usize>) -> Result<(), Error> {
+        match limit {
+            Some(limit) if self.total() > limit => Err(Error::MemoryLimit { needed: self.total(), limit }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Delay lines of comb filters built from `filters`, each at its own sample rate.
+    pub(crate) fn add_filters(&mut self, filters: &[FilterSettings], channels: usize) {
+        self.delay_lines += filters
+            .iter()
+            .map(|settings| comb_filter::delay_line_len(settings.max_delay_secs, settings.sample_rate_hz) * channels * SAMPLE_BYTES)
+            .sum::<usize>();
+    }
+
+    /// `count` buffers of one block per channel.
+    pub(crate) fn add_buffers(&mut self, count: usize, channels: usize, block_size: usize) {
+        self.buffers += count * channels * block_size * SAMPLE_BYTES;
+    }
+
+    pub(crate) fn add_audio(&mut self, channels: usize, num_frames: usize) {
+        self.audio += channels * num_frames * SAMPLE_BYTES;
+    }
+}
+
+impl fmt::Display for MemoryUsage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} (delay lines {}, buffers {}, audio {})",
+            format_bytes(self.total()),
+            format_bytes(self.delay_lines),
+            format_bytes(self.buffers),
+            format_bytes(self.audio)
+        )
+    }
+}
+
+/// Parse a byte count with an optional `K`, `M` or `G` suffix (powers of 1024), e.g. `512M`.
+pub fn parse_size(value: &str) -> Option<usize> {
+    let (number, multiplier) = match value.char_indices().last()? {
+        (i, 'K' | 'k') => (&value[..i], 1 << 10),
+        (i, 'M' | 'm') => (&value[..i], 1 << 20),
+        (i, 'G' | 'g') => (&value[..i], 1 << 30),
+        _ => (value, 1),
+    };
+    number.parse::<usize>().ok()?.checked_mul(multiplier)
+}
+
+pub fn format_bytes(bytes: usize) -> String {
+    match bytes {
+        0..=1023 => format!("{} B", bytes),
+        1024..=1048575 => format!("{:.1} KiB", bytes as f64 / 1024.0),
+        _ => format!("{:.1} MiB", bytes as f64 / 1048576.0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arrangement::{self, Arrangement, Region, Track};
+    use crate::comb_filter::FilterType;
+    use crate::drift::Drift;
+    use crate::render::{self, RenderConfig};
+    use crate::sample_format::OutputFormat;
+    use crate::test_wav::{temp_path, write_wav};
+
+    #[test]
+    fn test_memory_is_accounted_and_capped() {
+        let input_path = write_wav("input.wav", 1, 1000, [0.5; 100]);
+        let output_path = temp_path("output.wav");
+
+        // 100 frames of region audio, a 10 ms delay line (12 samples) and eight 8-frame buffers
+        let filter = FilterSettings { filter_type: FilterType::FIR, max_delay_secs: 0.01, sample_rate_hz: 1000.0, gain: 0.5, delay_secs: 0.005 };
+        let mut arrangement = Arrangement {
+            sample_rate: 1000,
+            num_channels: 1,
+            tracks: vec![Track { regions: vec![Region::new(&input_path, 0.0)], filters: vec![filter.clone()] }],
+            master_filters: Vec::new(),
+            block_size: 8,
+            output_format: OutputFormat::Float32,
+            max_memory_bytes: None,
+        };
+        let memory = arrangement::estimate_memory(&arrangement).expect("Failed to estimate memory");
+        assert_eq!((memory.audio, memory.delay_lines, memory.buffers), (400, 48, 256));
+        let report = arrangement::render(&arrangement, &output_path).expect("Failed to render arrangement");
+        assert_eq!(report.memory, memory);
+
+        // Over the cap, the render fails before writing anything
+        let _ = std::fs::remove_file(&output_path);
+        arrangement.max_memory_bytes = Some(memory.total() - 1);
+        match arrangement::render(&arrangement, &output_path) {
+            Err(Error::MemoryLimit { needed, limit }) => assert_eq!((needed, limit), (704, 703)),
+            other => panic!("Memory test failed: expected a memory limit error, got {:?}", other.map(|report| report.memory)),
+        }
+        assert!(!output_path.exists(), "Memory test failed: output written despite the memory limit.");
+        let config = RenderConfig { block_size: 8, max_memory_bytes: Some(100), ..RenderConfig::new(input_path, &output_path, vec![filter]) };
+        assert!(matches!(render::run(&config), Err(Error::MemoryLimit { needed: 176, limit: 100 })));
+        assert!(!output_path.exists(), "Memory test failed: output written despite the memory limit.");
+        assert_eq!(parse_size("512M"), Some(512 << 20));
+        assert_eq!(parse_size("2g"), Some(2 << 30));
+        assert_eq!(parse_size("1000"), Some(1000));
+        assert_eq!(parse_size("M"), None);
+    }
+
+    #[test]
+    fn test_render_memory_cap_at_true_allocation() {
+        // Settings made at 44.1 kHz, rendered at 1 kHz with drift: 10.5 ms of max delay is a
+        // 13-sample delay line per channel at the file's rate, plus four 8-frame buffers
+        let input_path = write_wav("input.wav", 2, 1000, [0.25; 40]);
+        let output_path = temp_path("output.wav");
+        let filter = FilterSettings { filter_type: FilterType::IIR, max_delay_secs: 0.0105, sample_rate_hz: 44100.0, gain: 0.5, delay_secs: 0.005 };
+        let config = |max_memory_bytes| RenderConfig {
+            block_size: 8,
+            drift: Some(Drift::new(3, 0.1).unwrap()),
+            max_memory_bytes: Some(max_memory_bytes),
+            ..RenderConfig::new(&input_path, &output_path, vec![filter.clone()])
+        };
+        let needed = 13 * 2 * 4 + 4 * 2 * 8 * 4;
+        let report = render::run(&config(needed)).expect("Render at the memory cap failed");
+        assert_eq!((report.memory.delay_lines, report.memory.total()), (104, needed));
+        assert!(matches!(render::run(&config(needed - 1)), Err(Error::MemoryLimit { needed: 360, limit: 359 })));
+    }
+}
@@ -0,0 +1,44 @@
+/// Level statistics for one channel of the most recently processed block.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct BlockStats {
+    pub peak: f32,
+    pub rms: f32,
+    /// Samples in the block whose magnitude reached or exceeded full scale (1.0).
+    pub clip_count: usize,
+}
+
+impl BlockStats {
+    pub fn measure(block: &[f32]) -> Self {
+        let mut meter = StatsAccumulator::default();
+        block.iter().for_each(|&sample| meter.add(sample));
+        meter.finish()
+    }
+}
+
+/// Builds `BlockStats` one sample at a time, so a processor can meter its output in the
+/// same loop that produces it instead of scanning the block again.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StatsAccumulator {
+    peak: f32,
+    sum_squares: f32,
+    clip_count: usize,
+    len: usize,
+}
+
+impl StatsAccumulator {
+    #[inline]
+    pub fn add(&mut self, sample: f32) {
+        let magnitude = sample.abs();
+        self.peak = self.peak.max(magnitude);
+        self.sum_squares += sample * sample;
+        if magnitude >= 1.0 {
+            self.clip_count += 1;
+        }
+        self.len += 1;
+    }
+
+    pub fn finish(self) -> BlockStats {
+        let rms = if self.len == 0 { 0.0 } else { (self.sum_squares / self.len as f32).sqrt() };
+        BlockStats { peak: self.peak, rms, clip_count: self.clip_count }
+    }
+}
@@ -0,0 +1,184 @@
+use std::fs;
+use std::path::Path;
+
+use crate::comb_filter::FilterType;
+use crate::effect::AudioEffect;
+use crate::error::Error;
+use crate::render::FilterSettings;
+
+// Filter chains stored as files, so a batch render can be repeated exactly.
+// The format is the small subset of TOML needed for a list of filters: one
+// `[[filter]]` table per filter, in chain order, holding `key = value` lines.
+// Comments start with '#'. There is no TOML crate in the build, so this is
+// parsed by hand; anything outside the subset is rejected with its line number.
+// All values are in physical units; `sample_rate_hz` only records the rate the
+// preset was made at, and renders recompute the delays for the file's own rate.
+//
+//     [[filter]]
+//     type = "IIR"
+//     max_delay_secs = 0.5
+//     sample_rate_hz = 44100
+//     gain = 0.5
+//     delay_secs = 0.01
+
+const KEYS: [&str; 5] = ["type", "max_delay_secs", "sample_rate_hz", "gain", "delay_secs"];
+
+/// Serialize `filters` in the preset format.
+pub fn to_string(filters: &[FilterSettings]) -> String {
+    let mut text = String::new();
+    for (i, settings) in filters.iter().enumerate() {
+        if i > 0 {
+            text.push('\n');
+        }
+        let filter_type = match settings.filter_type {
+            FilterType::FIR => "FIR",
+            FilterType::IIR => "IIR",
+        };
+        text.push_str("[[filter]]\n");
+        text.push_str(&format!("type = \"{}\"\n", filter_type));
+        text.push_str(&format!("max_delay_secs = {}\n", settings.max_delay_secs));
+        text.push_str(&format!("sample_rate_hz = {}\n", settings.sample_rate_hz));
+        text.push_str(&format!("gain = {}\n", settings.gain));
+        text.push_str(&format!("delay_secs = {}\n", settings.delay_secs));
+    }
+    text
+}
+
+/// Parse a preset. Every filter table must set all five keys.
+pub fn parse(text: &str) -> Result<Vec<FilterSettings>, Error> {
+    // Values of the table being read, indexed like KEYS, and the line of its header
+    let mut tables: Vec<(usize, [Option<&str>; 5])> = Vec::new();
+    for (i, raw_line) in text.lines().enumerate() {
+        let line_number = i + 1;
+        let line = raw_line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+        if line == "[[filter]]" {
+            tables.push((line_number, [None; 5]));
+            continue;
+        }
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| preset_error(line_number, format!("expected `key = value` or `[[filter]]`, found `{}`", line)))?;
+        let (key, value) = (key.trim(), value.trim());
+        let (_, values) = tables
+            .last_mut()
+            .ok_or_else(|| preset_error(line_number, format!("`{}` set before the first [[filter]]", key)))?;
+        let slot = KEYS
+            .iter()
+            .position(|&k| k == key)
+            .ok_or_else(|| preset_error(line_number, format!("unknown key `{}`", key)))?;
+        if values[slot].replace(value).is_some() {
+            return Err(preset_error(line_number, format!("`{}` set twice", key)));
+        }
+    }
+
+    tables
+        .into_iter()
+        .map(|(line_number, values)| {
+            let value = |slot: usize| values[slot].ok_or_else(|| preset_error(line_number, format!("filter is missing `{}`", KEYS[slot])));
+            let number = |slot: usize| {
+                let text = value(slot)?;
+                text.parse::<f32>()
+                    .map_err(|_| preset_error(line_number, format!("`{}` is not a number: {}", KEYS[slot], text)))
+            };
+            let filter_type = match value(0)? {
+                "\"FIR\"" => FilterType::FIR,
+                "\"IIR\"" => FilterType::IIR,
+                other => return Err(preset_error(line_number, format!("`type` must be \"FIR\" or \"IIR\", found {}", other))),
+            };
+            Ok(FilterSettings {
+                filter_type,
+                max_delay_secs: number(1)?,
+                sample_rate_hz: number(2)?,
+                gain: number(3)?,
+                delay_secs: number(4)?,
+            })
+        })
+        .collect()
+}
+
+/// Serialize the current value of every parameter of `effect` as one `[params]` table,
+/// for effects that have no settings type of their own:
+///
+/// ```text
+/// [params]
+/// drive_db = 12
+/// mix = 0.5
+/// ```
+pub fn params_to_string(effect: &dyn AudioEffect) -> String {
+    let mut text = String::from("[params]\n");
+    for &name in effect.param_names() {
+        if let Some(value) = effect.get_param_by_name(name) {
+            text.push_str(&format!("{} = {}\n", name, value));
+        }
+    }
+    text
+}
+
+/// Set the parameters listed in a `[params]` table on `effect`, in file order. Keys
+/// missing from the table keep their current value; unknown or rejected ones fail with
+/// their line number.
+pub fn apply_params(text: &str, effect: &mut dyn AudioEffect) -> Result<(), Error> {
+    let mut in_table = false;
+    for (i, raw_line) in text.lines().enumerate() {
+        let line_number = i + 1;
+        let line = raw_line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+        if line == "[params]" && !in_table {
+            in_table = true;
+            continue;
+        }
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| preset_error(line_number, format!("expected `key = value` or `[params]`, found `{}`", line)))?;
+        let (key, value) = (key.trim(), value.trim());
+        if !in_table {
+            return Err(preset_error(line_number, format!("`{}` set before [params]", key)));
+        }
+        let number = value
+            .parse::<f32>()
+            .map_err(|_| preset_error(line_number, format!("`{}` is not a number: {}", key, value)))?;
+        effect.set_param_by_name(key, number).map_err(|e| preset_error(line_number, e.to_string()))?;
+    }
+    Ok(())
+}
+
+pub fn load(path: &Path) -> Result<Vec<FilterSettings>, Error> {
+    parse(&fs::read_to_string(path)?)
+}
+
+pub fn save(path: &Path, filters: &[FilterSettings]) -> Result<(), Error> {
+    fs::write(path, to_string(filters))?;
+    Ok(())
+}
+
+fn preset_error(line: usize, message: String) -> Error {
+    Error::Preset { line, message }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_preset_round_trip() {
+        let filters = vec![
+            FilterSettings { filter_type: FilterType::IIR, max_delay_secs: 0.5, sample_rate_hz: 44100.0, gain: 0.5, delay_secs: 0.01 },
+            FilterSettings { filter_type: FilterType::FIR, max_delay_secs: 1.0, sample_rate_hz: 48000.0, gain: -0.3, delay_secs: 1.0 / 3.0 },
+        ];
+        let parsed = parse(&to_string(&filters)).expect("Failed to parse preset");
+        assert_eq!(parsed.len(), filters.len());
+        for (a, b) in filters.iter().zip(&parsed) {
+            assert_eq!(a.filter_type, b.filter_type);
+            assert_eq!((a.max_delay_secs, a.sample_rate_hz, a.gain, a.delay_secs), (b.max_delay_secs, b.sample_rate_hz, b.gain, b.delay_secs));
+        }
+        // Errors point at the offending line
+        let missing = "[[filter]]\ntype = \"FIR\"\ngain = 0.5\n";
+        assert!(matches!(parse(missing), Err(Error::Preset { line: 1, .. })));
+        assert!(matches!(parse("# chain\n[[filter]]\nspeed = 2\n"), Err(Error::Preset { line: 3, .. })));
+    }
+}
@@ -0,0 +1,210 @@
+use std::fmt;
+use std::path::Path;
+
+use hound::WavReader;
+
+use crate::error::Error;
+use crate::meter::BlockStats;
+use crate::sample_format;
+
+// Heuristic checks on a rendered file, for catching broken renders in batch jobs
+// without listening to every output. Each check compares one number against a
+// limit; none of them is a substitute for a proper loudness/true-peak meter.
+
+/// Limits the quality checks compare against.
+#[derive(Debug, Clone, PartialEq)]
+pub struct QualityThresholds {
+    /// Samples at or above full scale, summed over channels.
+    pub max_clipped_samples: usize,
+    /// Largest mean (DC offset) of any channel.
+    pub max_dc_offset: f32,
+    /// Largest peak between samples, estimated with 4x oversampling.
+    pub max_inter_sample_peak: f32,
+    /// Longest run of silence inside the file, in seconds. Leading and trailing
+    /// silence are not counted.
+    pub max_silence_secs: f32,
+    /// Samples below this magnitude count as silence.
+    pub silence_level: f32,
+}
+
+impl Default for QualityThresholds {
+    fn default() -> Self {
+        QualityThresholds {
+            max_clipped_samples: 0,
+            max_dc_offset: 0.01,
+            max_inter_sample_peak: 1.0,
+            max_silence_secs: 0.5,
+            silence_level: 1e-4,
+        }
+    }
+}
+
+impl QualityThresholds {
+    /// Override limits from `name=value` pairs separated by commas, e.g.
+    /// `clipped=10,dc=0.02,isp=1.1,silence=1.0,silence_level=0.001`.
+    pub fn parse_overrides(&mut self, spec: &str) -> Result<(), Error> {
+        for pair in spec.split(',').filter(|pair| !pair.is_empty()) {
+            let (name, value) = pair.split_once('=').ok_or_else(|| Error::UnknownParam { name: pair.to_string() })?;
+            let number: f32 = value.parse().map_err(|_| Error::UnknownParam { name: pair.to_string() })?;
+            match name {
+                "clipped" if number >= 0.0 => self.max_clipped_samples = number as usize,
+                "dc" => self.max_dc_offset = number,
+                "isp" => self.max_inter_sample_peak = number,
+                "silence" => self.max_silence_secs = number,
+                "silence_level" => self.silence_level = number,
+                "clipped" => return Err(Error::InvalidValue { param: "clipped", value: number }),
+                _ => return Err(Error::UnknownParam { name: name.to_string() }),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Result of one check: the measured value and the limit it must not exceed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct QualityCheck {
+    pub name: &'static str,
+    pub value: f32,
+    pub limit: f32,
+}
+
+impl QualityCheck {
+    pub fn passed(&self) -> bool {
+        self.value <= self.limit
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct QualityReport {
+    pub checks: Vec<QualityCheck>,
+}
+
+impl QualityReport {
+    pub fn passed(&self) -> bool {
+        self.checks.iter().all(QualityCheck::passed)
+    }
+}
+
+impl fmt::Display for QualityReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for check in &self.checks {
+            let verdict = if check.passed() { "pass" } else { "FAIL" };
+            writeln!(f, "{:<20} {:>12.6} (limit {:.6})  {}", check.name, check.value, check.limit, verdict)?;
+        }
+        write!(f, "Overall: {}", if self.passed() { "pass" } else { "FAIL" })
+    }
+}
+
+/// Run all checks on deinterleaved `channels`.
+pub fn analyze(channels: &[Vec<f32>], sample_rate_hz: f32, thresholds: &QualityThresholds) -> QualityReport {
+    let clipped: usize = channels.iter().map(|channel| BlockStats::measure(channel).clip_count).sum();
+    let dc_offset = channels
+        .iter()
+        .filter(|channel| !channel.is_empty())
+        .map(|channel| (channel.iter().map(|&x| x as f64).sum::<f64>() / channel.len() as f64).abs() as f32)
+        .fold(0.0, f32::max);
+    let inter_sample_peak = channels.iter().map(|channel| inter_sample_peak(channel)).fold(0.0, f32::max);
+    let silence_frames = channels
+        .iter()
+        .map(|channel| longest_inner_silence(channel, thresholds.silence_level))
+        .fold(0, usize::max);
+
+    QualityReport {
+        checks: vec![
+            QualityCheck { name: "clipped samples", value: clipped as f32, limit: thresholds.max_clipped_samples as f32 },
+            QualityCheck { name: "DC offset", value: dc_offset, limit: thresholds.max_dc_offset },
+            QualityCheck { name: "inter-sample peak", value: inter_sample_peak, limit: thresholds.max_inter_sample_peak },
+            QualityCheck {
+                name: "silence gap (s)",
+                value: silence_frames as f32 / sample_rate_hz,
+                limit: thresholds.max_silence_secs,
+            },
+        ],
+    }
+}
+
+/// Read a WAV file (any sample encoding) and run all checks on it.
+pub fn analyze_wav(path: &Path, thresholds: &QualityThresholds) -> Result<QualityReport, Error> {
+    let mut reader = WavReader::open(path)?;
+    let spec = reader.spec();
+    let mut channels = vec![Vec::new(); spec.channels as usize];
+    for (i, sample) in sample_format::samples_f32(&mut reader).enumerate() {
+        channels[i % spec.channels as usize].push(sample?);
+    }
+    Ok(analyze(&channels, spec.sample_rate as f32, thresholds))
+}
+
+// Half-length of the interpolation kernel, in samples
+const KERNEL_HALF_LEN: isize = 8;
+
+// Largest magnitude at the samples and at 1/4, 1/2 and 3/4 of the way between them,
+// reconstructed with a Hann-windowed sinc
+fn inter_sample_peak(samples: &[f32]) -> f32 {
+    let kernels: Vec<Vec<f32>> = [0.25_f32, 0.5, 0.75]
+        .iter()
+        .map(|&frac| {
+            (1 - KERNEL_HALF_LEN..=KERNEL_HALF_LEN)
+                .map(|k| {
+                    let x = frac - k as f32;
+                    let sinc = (std::f32::consts::PI * x).sin() / (std::f32::consts::PI * x);
+                    let window = 0.5 + 0.5 * (std::f32::consts::PI * x / KERNEL_HALF_LEN as f32).cos();
+                    sinc * window
+                })
+                .collect()
+        })
+        .collect();
+
+    let mut peak = samples.iter().fold(0.0_f32, |peak, x| peak.max(x.abs()));
+    for n in 0..samples.len() {
+        for kernel in &kernels {
+            let value: f32 = (1 - KERNEL_HALF_LEN..=KERNEL_HALF_LEN)
+                .zip(kernel)
+                .filter_map(|(k, weight)| samples.get(usize::try_from(n as isize + k).ok()?).map(|x| x * weight))
+                .sum();
+            peak = peak.max(value.abs());
+        }
+    }
+    peak
+}
+
+// Longest run of samples below `level` that has non-silent samples on both sides
+fn longest_inner_silence(samples: &[f32], level: f32) -> usize {
+    let Some(first) = samples.iter().position(|x| x.abs() >= level) else {
+        return 0;
+    };
+    let last = samples.iter().rposition(|x| x.abs() >= level).unwrap_or(first);
+    let mut longest = 0;
+    let mut run = 0;
+    for x in &samples[first..=last] {
+        if x.abs() < level {
+            run += 1;
+            longest = longest.max(run);
+        } else {
+            run = 0;
+        }
+    }
+    longest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quality_report_heuristics() {
+        // A quarter-rate sine sampled 45 degrees off its peaks never has a sample above 0.64,
+        // but the reconstructed waveform reaches 0.9 between samples
+        let sine: Vec<f32> = (0..256).map(|n| 0.9 * (std::f32::consts::FRAC_PI_2 * n as f32 + std::f32::consts::FRAC_PI_4).sin()).collect();
+        let report = analyze(std::slice::from_ref(&sine), 1000.0, &QualityThresholds::default());
+        assert!(report.passed(), "Quality test failed: clean sine flagged:\n{}", report);
+        let isp = report.checks.iter().find(|c| c.name == "inter-sample peak").unwrap().value;
+        assert!((isp - 0.9).abs() < 0.02, "Quality test failed: inter-sample peak {} is not close to 0.9.", isp);
+
+        // 0.6 s of silence in the middle, with DC added to everything else
+        let mut gappy: Vec<f32> = sine.iter().map(|x| 0.5 * x + 0.1).collect();
+        gappy.splice(100..100, std::iter::repeat_n(0.0, 600));
+        let report = analyze(&[gappy], 1000.0, &QualityThresholds::default());
+        let failed: Vec<&str> = report.checks.iter().filter(|c| !c.passed()).map(|c| c.name).collect();
+        assert_eq!(failed, ["DC offset", "silence gap (s)"]);
+    }
+}
@@ -0,0 +1,530 @@
+use std::fs::File;
+use std::io::{BufWriter, Seek, Write};
+use std::path::{Path, PathBuf};
+
+use hound::{SampleFormat, WavReader, WavSpec, WavWriter};
+
+use crate::checksum::SampleHasher;
+use crate::comb_filter::{CombFilter, FilterParam, FilterType};
+use crate::drift::{Drift, DriftOffset};
+use crate::effect::{AudioEffect, EffectChain, MAX_CHANNELS};
+use crate::envelope::Envelope;
+use crate::error::Error;
+use crate::memory::MemoryUsage;
+use crate::meter::BlockStats;
+use crate::sample_format::{self, OutputFormat};
+use crate::state;
+use crate::units;
+
+/// Constructor arguments of one comb filter in the render chain. Times are in seconds,
+/// so the same settings work at any sample rate.
+#[derive(Debug, Clone)]
+pub struct FilterSettings {
+    pub filter_type: FilterType,
+    pub max_delay_secs: f32,
+    /// Rate the settings were written for. Renders prepare every filter at the rate of
+    /// the audio being rendered instead, recomputing the delays in samples.
+    pub sample_rate_hz: f32,
+    pub gain: f32,
+    pub delay_secs: f32,
+}
+
+/// Everything needed to render one file through a chain of comb filters.
+#[derive(Debug, Clone)]
+pub struct RenderConfig {
+    pub input_path: PathBuf,
+    pub output_path: PathBuf,
+    /// Filters applied in series, first to last.
+    pub filters: Vec<FilterSettings>,
+    pub block_size: usize,
+    /// Sample encoding of the output file; `None` keeps the input file's encoding.
+    pub output_format: Option<OutputFormat>,
+    /// Parameter changes over the course of the render.
+    pub automation: Vec<Automation>,
+    /// Seeded random offsets on the non-automated filter parameters; `None` renders
+    /// the settings exactly.
+    pub drift: Option<Drift>,
+    /// Fail before allocating anything if the render would need more bytes than this.
+    pub max_memory_bytes: Option<usize>,
+    /// Start from the processor state in this file (see `state`) instead of silence.
+    pub load_state_path: Option<PathBuf>,
+    /// Save the processor state here at the end, for a render that continues this one.
+    /// The join is seamless when this render's length is a multiple of `block_size`;
+    /// otherwise the zero padding of the last block is part of the saved state.
+    pub save_state_path: Option<PathBuf>,
+    /// Also write stems next to the output file: the dry input (`<name>.dry.wav`), the
+    /// wet part, i.e. output minus input (`<name>.wet.wav`), and the output of each
+    /// filter in the chain (`<name>.filter<index>.wav`).
+    pub stems: bool,
+    /// Clamp automation values outside a filter's range instead of failing the render;
+    /// `RenderReport::clamped_params` counts them.
+    pub permissive: bool,
+}
+
+impl RenderConfig {
+    /// Render of `input_path` into `output_path` through `filters` in blocks of 1024
+    /// frames, keeping the input encoding, with nothing else enabled.
+    pub fn new(input_path: impl Into<PathBuf>, output_path: impl Into<PathBuf>, filters: Vec<FilterSettings>) -> Self {
+        RenderConfig {
+            input_path: input_path.into(),
+            output_path: output_path.into(),
+            filters,
+            block_size: 1024,
+            output_format: None,
+            automation: Vec::new(),
+            drift: None,
+            max_memory_bytes: None,
+            load_state_path: None,
+            save_state_path: None,
+            stems: false,
+            permissive: false,
+        }
+    }
+}
+
+/// A parameter of one filter in the chain following an envelope, with time measured
+/// from the start of the input file.
+#[derive(Debug, Clone)]
+pub struct Automation {
+    /// Index into `RenderConfig::filters`.
+    pub filter_index: usize,
+    pub param: FilterParam,
+    pub envelope: Envelope,
+}
+
+
+/// What happened during a render, for callers that want stats instead of log output.
+#[derive(Debug, Clone, Default)]
+pub struct RenderReport {
+    pub num_channels: usize,
+    /// Frames (samples per channel) written to the output file.
+    pub num_frames: usize,
+    pub peak: f32,
+    pub num_clipped: usize,
+    /// Output RMS of each processed block, averaged over channels.
+    pub block_rms: Vec<f32>,
+    /// Checksum of the samples as written to the output file, see `checksum`.
+    pub output_hash: u64,
+    /// Parameter offsets applied by `RenderConfig::drift`.
+    pub drift_offsets: Vec<DriftOffset>,
+    /// What the render allocated, as estimated before it started.
+    pub memory: MemoryUsage,
+    /// Stem files written, see `RenderConfig::stems`.
+    pub stem_paths: Vec<PathBuf>,
+    /// Parameter values each filter clamped in permissive mode, in chain order.
+    pub clamped_params: Vec<usize>,
+}
+
+/// Read the input file, run it through the filter chain block by block and write the output file.
+pub fn run(config: &RenderConfig) -> Result<RenderReport, Error> {
+    let mut reader = WavReader::open(&config.input_path)?;
+    let spec = reader.spec();
+    let channels = spec.channels as usize;
+    let block_size = config.block_size;
+    let sample_rate_hz = spec.sample_rate as f32;
+
+    let mut filters = at_sample_rate(&config.filters, sample_rate_hz);
+    if let Some(automation) = config.automation.iter().find(|automation| automation.filter_index >= filters.len()) {
+        return Err(Error::InvalidValue { param: "automation filter index", value: automation.filter_index as f32 });
+    }
+    let automated: Vec<(usize, FilterParam)> = config.automation.iter().map(|a| (a.filter_index, a.param)).collect();
+    let drift_offsets = config.drift.map_or(Vec::new(), |drift| drift.apply(&mut filters, &automated));
+
+    // Estimated from the settings the chain is built with
+    let mut memory = MemoryUsage::default();
+    memory.add_filters(&filters, channels);
+    // Input and output blocks here and the chain's two scratch buffers
+    memory.add_buffers(4, channels, block_size);
+    if config.stems {
+        // Wet block and one tap block per filter
+        memory.add_buffers(1 + filters.len(), channels, block_size);
+    }
+    memory.check(config.max_memory_bytes)?;
+
+    let out_spec = config.output_format.map_or(spec, |format| format.apply(spec));
+    let writer = WavWriter::create(&config.output_path, out_spec)?;
+
+    // Parameters that never change are unaffected by the smoothing time
+    let smoothing_secs = if config.automation.is_empty() { 0.0 } else { units::samples_to_secs(block_size, sample_rate_hz) };
+    let mut chain = build_chain(&filters, channels, block_size, smoothing_secs, config.permissive)?;
+    // Automated filters start at their envelope's initial value and glide between blocks;
+    // the reset skips the glide from the filter's own setting to the initial value
+    for automation in &config.automation {
+        chain
+            .effect_mut(automation.filter_index)
+            .set_param_by_name(automation.param.name(), automation.envelope.value_at(0.0))?;
+    }
+    chain.reset();
+    if let Some(path) = &config.load_state_path {
+        state::load(path, &mut chain)?;
+    }
+    let mut frames_done = 0;
+
+    // Initialize buffers for processing
+    let mut input_blocks: Vec<Vec<f32>> = vec![vec![0.0; block_size]; channels];
+    let mut output_blocks: Vec<Vec<f32>> = vec![vec![0.0; block_size]; channels];
+    let mut output = BlockWriter::new(writer, channels);
+    let mut stems = if config.stems { Some(Stems::create(&config.output_path, out_spec, config.filters.len(), block_size)?) } else { None };
+
+    // Stream the file through fixed-size blocks, reusing the same buffers for every block
+    let mut samples = sample_format::samples_f32(&mut reader);
+    loop {
+        // Convert and separate samples into channels
+        let mut actual_block_size = 0; // Actual number of samples per channel in this block
+        'fill: for frame in 0..block_size {
+            for input_block in input_blocks.iter_mut() {
+                match samples.next() {
+                    Some(sample) => input_block[frame] = sample?,
+                    None => break 'fill,
+                }
+            }
+            actual_block_size += 1;
+        }
+        if actual_block_size == 0 {
+            break;
+        }
+
+        // Zero the rest of a short final block
+        for channel_data in &mut input_blocks {
+            channel_data[actual_block_size..].fill(0.0);
+        }
+
+        // Aim automated parameters at their value at the end of the block; the filters
+        // ramp there linearly over the block, so they follow the envelope sample by sample
+        // with its breakpoints rounded to block boundaries.
+        let block_end_secs = units::samples_to_secs(frames_done + block_size, sample_rate_hz);
+        for automation in &config.automation {
+            chain
+                .effect_mut(automation.filter_index)
+                .set_param_by_name(automation.param.name(), automation.envelope.value_at(block_end_secs))?;
+        }
+
+        // Process each block
+        let input_slices: Vec<&[f32]> = input_blocks.iter().map(|v| v.as_slice()).collect();
+        let mut output_slices: Vec<&mut [f32]> = output_blocks.iter_mut().map(|v| v.as_mut_slice()).collect();
+        match &mut stems {
+            Some(stems) => {
+                let taps = &mut stems.tap_blocks;
+                chain.process_tapped(&input_slices, &mut output_slices, |index, block| {
+                    for (tap, channel) in taps[index].iter_mut().zip(block) {
+                        tap[..channel.len()].copy_from_slice(channel);
+                    }
+                });
+                stems.write_block(&input_blocks, &output_blocks, actual_block_size)?;
+            }
+            None => chain.process(&input_slices, &mut output_slices),
+        }
+        output.write_block(&output_blocks, actual_block_size, metered_stats(&chain, actual_block_size, block_size))?;
+        frames_done += actual_block_size;
+    }
+
+    if let Some(path) = &config.save_state_path {
+        state::save(path, &chain)?;
+    }
+    let mut report = output.finish()?;
+    report.drift_offsets = drift_offsets;
+    report.clamped_params = (0..chain.len()).map(|index| chain.effect(index).num_clamped()).collect();
+    report.memory = memory;
+    if let Some(stems) = stems {
+        report.stem_paths = stems.finish()?;
+    }
+    Ok(report)
+}
+
+/// `<name>.<stem>.wav` next to `output_path`.
+pub fn stem_path(output_path: &Path, stem: &str) -> PathBuf {
+    let name = output_path.file_stem().map_or_else(|| "output".into(), |name| name.to_string_lossy());
+    output_path.with_file_name(format!("{}.{}.wav", name, stem))
+}
+
+// Writers and block buffers for the stems of a render
+struct Stems {
+    paths: Vec<PathBuf>,
+    // Dry, wet, then one per filter
+    writers: Vec<BlockWriter<BufWriter<File>>>,
+    wet_block: Vec<Vec<f32>>,
+    tap_blocks: Vec<Vec<Vec<f32>>>,
+}
+
+impl Stems {
+    fn create(output_path: &Path, spec: WavSpec, num_filters: usize, block_size: usize) -> Result<Self, Error> {
+        let channels = spec.channels as usize;
+        let names = ["dry".to_string(), "wet".to_string()].into_iter().chain((0..num_filters).map(|index| format!("filter{}", index)));
+        let paths: Vec<PathBuf> = names.map(|name| stem_path(output_path, &name)).collect();
+        let writers = paths
+            .iter()
+            .map(|path| Ok(BlockWriter::new(WavWriter::create(path, spec)?, channels)))
+            .collect::<Result<Vec<_>, Error>>()?;
+        Ok(Stems {
+            paths,
+            writers,
+            wet_block: vec![vec![0.0; block_size]; channels],
+            tap_blocks: vec![vec![vec![0.0; block_size]; channels]; num_filters],
+        })
+    }
+
+    fn write_block(&mut self, input: &[Vec<f32>], output: &[Vec<f32>], len: usize) -> Result<(), Error> {
+        for ((wet, dry), out) in self.wet_block.iter_mut().zip(input).zip(output) {
+            for n in 0..len {
+                wet[n] = out[n] - dry[n];
+            }
+        }
+        let blocks = [input, &self.wet_block].into_iter().chain(self.tap_blocks.iter().map(Vec::as_slice));
+        for (writer, block) in self.writers.iter_mut().zip(blocks) {
+            writer.write_block(block, len, None)?;
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> Result<Vec<PathBuf>, Error> {
+        for writer in self.writers {
+            writer.finish()?;
+        }
+        Ok(self.paths)
+    }
+}
+
+/// Copy of `filters` prepared for `sample_rate_hz`.
+pub(crate) fn at_sample_rate(filters: &[FilterSettings], sample_rate_hz: f32) -> Vec<FilterSettings> {
+    filters.iter().map(|settings| FilterSettings { sample_rate_hz, ..settings.clone() }).collect()
+}
+
+/// Chain of comb filters built from `filters`, in order, with parameter changes ramped
+/// over `smoothing_secs` and, if `permissive`, clamped to each filter's range. The last
+/// filter meters its output for `metered_stats`.
+pub(crate) fn build_chain(
+    filters: &[FilterSettings],
+    channels: usize,
+    block_size: usize,
+    smoothing_secs: f32,
+    permissive: bool,
+) -> Result<EffectChain, Error> {
+    if channels > MAX_CHANNELS {
+        return Err(Error::InvalidValue { param: "channels", value: channels as f32 });
+    }
+    let mut chain = EffectChain::new(channels, block_size);
+    for settings in filters {
+        let mut comb_filter = CombFilter::new(
+            settings.filter_type,
+            settings.max_delay_secs,
+            settings.sample_rate_hz,
+            channels,
+            settings.gain,
+            settings.delay_secs,
+        )?;
+        if smoothing_secs > 0.0 {
+            comb_filter.set_smoothing_time(smoothing_secs);
+        }
+        comb_filter.set_permissive(permissive);
+        if chain.len() + 1 == filters.len() {
+            comb_filter.set_metering(true);
+        }
+        chain.push(Box::new(comb_filter));
+    }
+    Ok(chain)
+}
+
+/// Output stats of the chain's last block as metered by its last filter, so the writer
+/// doesn't measure the block again. Only a full block matches the `len` frames written.
+pub(crate) fn metered_stats(chain: &EffectChain, len: usize, block_size: usize) -> Option<&[BlockStats]> {
+    if len != block_size {
+        return None;
+    }
+    chain.len().checked_sub(1).and_then(|last| chain.effect(last).block_stats())
+}
+
+/// Writes processed blocks to a WAV file while collecting the `RenderReport`.
+pub(crate) struct BlockWriter<W: Write + Seek> {
+    writer: WavWriter<W>,
+    hasher: SampleHasher,
+    report: RenderReport,
+}
+
+impl<W: Write + Seek> BlockWriter<W> {
+    pub(crate) fn new(writer: WavWriter<W>, channels: usize) -> Self {
+        BlockWriter {
+            writer,
+            hasher: SampleHasher::new(),
+            report: RenderReport { num_channels: channels, ..Default::default() },
+        }
+    }
+
+    /// Write the first `len` frames of `blocks` (one per channel), taking their level
+    /// stats from `stats` if they were already metered and measuring them otherwise.
+    pub(crate) fn write_block(&mut self, blocks: &[Vec<f32>], len: usize, stats: Option<&[BlockStats]>) -> Result<(), Error> {
+        let report = &mut self.report;
+        let measured: Vec<BlockStats>;
+        let stats = match stats {
+            Some(stats) => stats,
+            None => {
+                measured = blocks.iter().map(|v| BlockStats::measure(&v[..len])).collect();
+                &measured
+            }
+        };
+        report.num_clipped += stats.iter().map(|s| s.clip_count).sum::<usize>();
+        report.peak = stats.iter().map(|s| s.peak).fold(report.peak, f32::max);
+        report.block_rms.push(stats.iter().map(|s| s.rms).sum::<f32>() / stats.len() as f32);
+
+        // Write processed samples back, interleaving channels
+        let out_spec = self.writer.spec();
+        for i in 0..len {
+            for block in blocks {
+                match out_spec.sample_format {
+                    SampleFormat::Float => {
+                        self.writer.write_sample(block[i])?;
+                        self.hasher.update(block[i]);
+                    }
+                    SampleFormat::Int => {
+                        let sample = sample_format::quantize(block[i], out_spec.bits_per_sample);
+                        self.writer.write_sample(sample)?;
+                        self.hasher.update(sample_format::dequantize(sample, out_spec.bits_per_sample));
+                    }
+                }
+            }
+        }
+        report.num_frames += len;
+        Ok(())
+    }
+
+    pub(crate) fn finish(mut self) -> Result<RenderReport, Error> {
+        self.writer.finalize()?;
+        self.report.output_hash = self.hasher.finish();
+        Ok(self.report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_wav::{read_wav, temp_path, write_wav};
+    use crate::preset;
+
+    #[test]
+    fn test_render_follows_gain_automation() {
+        // DC through an FIR comb with a one-sample delay gives 1 + gain, so the output traces the gain envelope
+        let output_path = temp_path("automation.wav");
+        let filter = FilterSettings { filter_type: FilterType::FIR, max_delay_secs: 0.01, sample_rate_hz: 1000.0, gain: 0.5, delay_secs: 0.001 };
+        let config = RenderConfig {
+            block_size: 64,
+            automation: vec![Automation { filter_index: 0, param: FilterParam::Gain, envelope: Envelope::new(vec![(0.0, 0.0), (0.64, 1.0)]).unwrap() }],
+            ..RenderConfig::new(write_wav("dc.wav", 1, 1000, [1.0; 640]), &output_path, vec![filter])
+        };
+        run(&config).expect("Failed to render");
+        let output = read_wav(&output_path);
+        for (n, &y) in output.iter().enumerate().skip(1) {
+            let expected = 1.0 + n as f32 / 640.0;
+            assert!((y - expected).abs() < 0.01, "Automation test failed: sample {} is {}, expected {}.", n, y, expected);
+        }
+    }
+
+    #[test]
+    fn test_preset_renders_alike_at_any_sample_rate() {
+        // A preset made at 44.1 kHz, loaded and rendered at other rates
+        let filters = preset::parse(
+            "[[filter]]\ntype = \"FIR\"\nmax_delay_secs = 0.02\nsample_rate_hz = 44100\ngain = 0.5\ndelay_secs = 0.01\n\n\
+             [[filter]]\ntype = \"IIR\"\nmax_delay_secs = 0.02\nsample_rate_hz = 44100\ngain = 0.3\ndelay_secs = 0.01\n",
+        )
+        .expect("Failed to parse preset");
+        let render_at = |sample_rate: u32, signal: &dyn Fn(f32) -> f32| {
+            let input_path = write_wav("input.wav", 1, sample_rate, (0..sample_rate / 2).map(|n| signal(n as f32 / sample_rate as f32)));
+            let output_path = temp_path("output.wav");
+            run(&RenderConfig::new(input_path, &output_path, filters.clone())).expect("Failed to render");
+            read_wav(&output_path)
+        };
+
+        let impulse = |t: f32| if t == 0.0 { 1.0 } else { 0.0 };
+        let sine = |t: f32| 0.25 * (std::f32::consts::TAU * 440.0 * t).sin();
+        let mut levels = Vec::new();
+        for sample_rate in [44100, 48000, 96000] {
+            // Both echoes land 10 ms after the impulse, whatever the rate
+            let output = render_at(sample_rate, &impulse);
+            let echo = units::secs_to_samples(0.01, sample_rate as f32);
+            assert!((output[echo] - 0.8).abs() < 1e-6, "Sample rate test failed: no 10 ms echo at {} Hz.", sample_rate);
+            assert!(output[1..echo].iter().all(|&x| x == 0.0), "Sample rate test failed: early echo at {} Hz.", sample_rate);
+            let output = render_at(sample_rate, &sine);
+            let steady = &output[output.len() / 2..];
+            levels.push(units::linear_to_db((steady.iter().map(|x| x * x).sum::<f32>() / steady.len() as f32).sqrt()));
+        }
+        // A steady tone comes out at the same level
+        for level in &levels {
+            assert!((level - levels[0]).abs() < 0.1, "Sample rate test failed: levels {:?} dB differ.", levels);
+        }
+    }
+
+    #[test]
+    fn test_render_writes_stems() {
+        let input_path = write_wav("input.wav", 2, 1000, (0..500).map(|n| if n % 97 == 0 { 0.5 } else { 0.0 }));
+        let fir = FilterSettings { filter_type: FilterType::FIR, max_delay_secs: 0.1, sample_rate_hz: 1000.0, gain: 0.5, delay_secs: 0.01 };
+        let iir = FilterSettings { filter_type: FilterType::IIR, max_delay_secs: 0.1, sample_rate_hz: 1000.0, gain: 0.7, delay_secs: 0.03 };
+        let render_to = |output_path: &Path, filters: Vec<FilterSettings>, stems: bool| {
+            let config = RenderConfig { block_size: 64, stems, ..RenderConfig::new(&input_path, output_path, filters) };
+            run(&config).expect("Failed to render")
+        };
+
+        let output_path = temp_path("stems.wav");
+        let report = render_to(&output_path, vec![fir.clone(), iir], true);
+        let expected_paths: Vec<PathBuf> = ["dry", "wet", "filter0", "filter1"].iter().map(|stem| stem_path(&output_path, stem)).collect();
+        assert_eq!(report.stem_paths, expected_paths);
+        assert!(report.stem_paths[0].file_name().unwrap().to_string_lossy().ends_with("stems.dry.wav"));
+        let output = read_wav(&output_path);
+        let dry = read_wav(&report.stem_paths[0]);
+        let wet = read_wav(&report.stem_paths[1]);
+        assert_eq!(dry, read_wav(&input_path), "Stems test failed: dry stem differs from the input.");
+        for ((d, w), y) in dry.iter().zip(&wet).zip(&output) {
+            assert!((d + w - y).abs() < 1e-6, "Stems test failed: dry + wet {} differs from output {}.", d + w, y);
+        }
+        // Each filter stem is the chain output up to that filter
+        let first_path = temp_path("first.wav");
+        let first_only = render_to(&first_path, vec![fir], false);
+        assert!(first_only.stem_paths.is_empty());
+        assert_eq!(read_wav(&report.stem_paths[2]), read_wav(&first_path));
+        assert_eq!(read_wav(&report.stem_paths[3]), output);
+    }
+
+    #[test]
+    fn test_render_delay_longer_than_block() {
+        // 100 ms of delay at 1 kHz is 100 samples, rendered 16 frames at a time
+        let output_path = temp_path("long_delay.wav");
+        let filter = FilterSettings { filter_type: FilterType::FIR, max_delay_secs: 0.2, sample_rate_hz: 1000.0, gain: 0.5, delay_secs: 0.1 };
+        let input_path = write_wav("impulse.wav", 1, 1000, (0..200).map(|n| if n == 0 { 1.0 } else { 0.0 }));
+        let config = RenderConfig { block_size: 16, ..RenderConfig::new(input_path, &output_path, vec![filter]) };
+        let report = run(&config).expect("Failed to render");
+        assert_eq!(report.num_frames, 200);
+        let echoes: Vec<usize> = read_wav(&output_path).iter().enumerate().filter(|(_, &x)| x != 0.0).map(|(n, _)| n).collect();
+        assert_eq!(echoes, [0, 100]);
+    }
+
+    #[test]
+    fn test_render_report_levels_match_output() {
+        // Three full blocks and a short one, loud enough to clip
+        let output_path = temp_path("levels.wav");
+        let filter = FilterSettings { filter_type: FilterType::IIR, max_delay_secs: 0.01, sample_rate_hz: 1000.0, gain: 0.8, delay_secs: 0.005 };
+        let input_path = write_wav("sine.wav", 1, 1000, (0..200).map(|n| 0.5 + 0.2 * (n as f32 * 0.3).sin()));
+        let report = run(&RenderConfig { block_size: 64, ..RenderConfig::new(input_path, &output_path, vec![filter]) }).expect("Failed to render");
+        let output = read_wav(&output_path);
+        let stats: Vec<BlockStats> = output.chunks(64).map(BlockStats::measure).collect();
+        assert_eq!(report.block_rms, stats.iter().map(|s| s.rms).collect::<Vec<_>>());
+        assert_eq!(report.peak, stats.iter().map(|s| s.peak).fold(0.0, f32::max));
+        assert_eq!(report.num_clipped, stats.iter().map(|s| s.clip_count).sum::<usize>());
+        assert!(report.num_clipped > 0, "Level test failed: the render should clip.");
+    }
+
+    #[test]
+    fn test_permissive_render_counts_clamped_params() {
+        // IIR gain automated from 0.5 up to 2.5; the eight block targets from 1.1 up are out of range
+        let fir = FilterSettings { filter_type: FilterType::FIR, max_delay_secs: 0.01, sample_rate_hz: 1000.0, gain: 0.5, delay_secs: 0.002 };
+        let iir = FilterSettings { filter_type: FilterType::IIR, max_delay_secs: 0.01, sample_rate_hz: 1000.0, gain: 0.5, delay_secs: 0.003 };
+        let gain_ramp = Automation { filter_index: 1, param: FilterParam::Gain, envelope: Envelope::new(vec![(0.0, 0.5), (0.64, 2.5)]).unwrap() };
+        let output_path = temp_path("permissive.wav");
+        let config = RenderConfig {
+            block_size: 64,
+            automation: vec![gain_ramp],
+            ..RenderConfig::new(write_wav("noise.wav", 1, 1000, (0..640).map(|n| 0.1 * (n as f32 * 1.7).sin())), &output_path, vec![fir, iir])
+        };
+        assert!(matches!(run(&config), Err(Error::InvalidValue { param: "gain", .. })));
+
+        let report = run(&RenderConfig { permissive: true, ..config }).expect("Permissive render should clamp");
+        assert_eq!(report.clamped_params, [0, 8]);
+        assert!(read_wav(&output_path).iter().all(|x| x.is_finite()), "Permissive render test failed: clamped gain produced non-finite output.");
+    }
+}
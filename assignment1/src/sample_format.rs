@@ -0,0 +1,91 @@
+use std::io::Read;
+
+use hound::{SampleFormat, WavReader, WavSpec};
+
+// Conversion between WAV sample encodings and the f32 samples the filters work on.
+// Integer samples are scaled by the largest positive value (e.g. 32767 for 16 bit),
+// so a full-scale positive sample maps to exactly 1.0.
+
+/// Sample encoding of an output file.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutputFormat {
+    Int16,
+    Int24,
+    Int32,
+    Float32,
+}
+
+impl OutputFormat {
+    /// Parse an `--out-format` value: `16`, `24`, `32` or `float`.
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "16" => Some(OutputFormat::Int16),
+            "24" => Some(OutputFormat::Int24),
+            "32" => Some(OutputFormat::Int32),
+            "float" | "f32" => Some(OutputFormat::Float32),
+            _ => None,
+        }
+    }
+
+    /// `spec` with its sample encoding replaced by this format.
+    pub fn apply(self, spec: WavSpec) -> WavSpec {
+        let (sample_format, bits_per_sample) = match self {
+            OutputFormat::Int16 => (SampleFormat::Int, 16),
+            OutputFormat::Int24 => (SampleFormat::Int, 24),
+            OutputFormat::Int32 => (SampleFormat::Int, 32),
+            OutputFormat::Float32 => (SampleFormat::Float, 32),
+        };
+        WavSpec { sample_format, bits_per_sample, ..spec }
+    }
+}
+
+// Largest sample value of the integer format, in f64 because 2^31 - 1 doesn't fit an f32
+fn int_full_scale(bits_per_sample: u16) -> f64 {
+    ((1_i64 << (bits_per_sample - 1)) - 1) as f64
+}
+
+/// Iterate over all samples of `reader` (interleaved) as f32, whatever the file's encoding.
+pub fn samples_f32<'a, R: Read + 'a>(
+    reader: &'a mut WavReader<R>,
+) -> Box<dyn Iterator<Item = Result<f32, hound::Error>> + 'a> {
+    let spec = reader.spec();
+    match spec.sample_format {
+        SampleFormat::Float => Box::new(reader.samples::<f32>()),
+        SampleFormat::Int => {
+            let bits_per_sample = spec.bits_per_sample;
+            Box::new(reader.samples::<i32>().map(move |s| s.map(|s| dequantize(s, bits_per_sample))))
+        }
+    }
+}
+
+/// Convert `sample` to an integer sample of `bits_per_sample`, truncating towards
+/// zero and saturating at the format's limits.
+pub fn quantize(sample: f32, bits_per_sample: u16) -> i32 {
+    let scale = int_full_scale(bits_per_sample);
+    (sample as f64 * scale).clamp(-scale - 1.0, scale) as i32
+}
+
+/// The f32 value a reader will decode `sample` back to.
+pub fn dequantize(sample: i32, bits_per_sample: u16) -> f32 {
+    (sample as f64 / int_full_scale(bits_per_sample)) as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quantize_round_trip_at_full_scale() {
+        for bits_per_sample in [8, 16, 24, 32] {
+            let max = ((1_i64 << (bits_per_sample - 1)) - 1) as i32;
+            let round_trip = |sample: f32| dequantize(quantize(sample, bits_per_sample), bits_per_sample);
+            assert_eq!((quantize(1.0, bits_per_sample), quantize(-1.0, bits_per_sample)), (max, -max), "{}-bit full scale", bits_per_sample);
+            assert_eq!((round_trip(1.0), round_trip(-1.0)), (1.0, -1.0), "{}-bit full scale round trip", bits_per_sample);
+            // Above full scale saturates instead of wrapping around
+            assert_eq!((quantize(1.5, bits_per_sample), quantize(-1.5, bits_per_sample)), (max, -max - 1), "{}-bit saturation", bits_per_sample);
+            assert_eq!(round_trip(1.5), 1.0, "{}-bit saturation round trip", bits_per_sample);
+            assert!(round_trip(-1.5) <= -1.0 && round_trip(-1.5) + 1.0 >= -1.5 / max as f32, "{}-bit negative saturation round trip", bits_per_sample);
+            assert!((round_trip(0.3) - 0.3).abs() <= 1.0 / max as f32, "{}-bit round trip of 0.3", bits_per_sample);
+        }
+    }
+}
@@ -0,0 +1,135 @@
+use crate::error::Error;
+use crate::state::{StateReader, StateWriter};
+use crate::units::secs_to_samples;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SmoothingType {
+    /// Straight line from the current value to the target over the smoothing time.
+    Linear,
+    /// One-pole lowpass; the smoothing time is the time constant (~63% of the step).
+    Exponential,
+    /// `n` one-pole stages in series, which gives an S-shaped ramp with no corner at the start.
+    MultiStage(usize),
+}
+
+pub struct Smoother {
+    smoothing_type: SmoothingType,
+    sample_rate_hz: f32,
+    time_secs: f32,
+    target: f32,
+    // Linear ramp state
+    current: f32,
+    step: f32,
+    steps_left: usize,
+    // One-pole state, one entry per stage
+    stages: Vec<f32>,
+    coeff: f32,
+}
+
+impl Smoother {
+    pub fn new(smoothing_type: SmoothingType, sample_rate_hz: f32, time_secs: f32, value: f32) -> Self {
+        let num_stages = match smoothing_type {
+            SmoothingType::Linear => 0,
+            SmoothingType::Exponential => 1,
+            SmoothingType::MultiStage(n) => n.max(1),
+        };
+        let mut smoother = Smoother {
+            smoothing_type,
+            sample_rate_hz,
+            time_secs,
+            target: value,
+            current: value,
+            step: 0.0,
+            steps_left: 0,
+            stages: vec![value; num_stages],
+            coeff: 0.0,
+        };
+        smoother.update_coeff();
+        smoother
+    }
+
+    pub fn set_target(&mut self, target: f32) {
+        self.target = target;
+        let ramp_samples = secs_to_samples(self.time_secs, self.sample_rate_hz);
+        if ramp_samples == 0 {
+            self.snap_to_target();
+        } else {
+            self.step = (target - self.current) / ramp_samples as f32;
+            self.steps_left = ramp_samples;
+        }
+    }
+
+    /// Jump to the target immediately, skipping any ramp in progress.
+    pub fn snap_to_target(&mut self) {
+        self.current = self.target;
+        self.steps_left = 0;
+        self.stages.iter_mut().for_each(|x| *x = self.target);
+    }
+
+    pub fn set_time(&mut self, time_secs: f32) {
+        self.time_secs = time_secs.max(0.0);
+        self.update_coeff();
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate_hz: f32) {
+        self.sample_rate_hz = sample_rate_hz;
+        self.update_coeff();
+    }
+
+    pub fn next_value(&mut self) -> f32 {
+        match self.smoothing_type {
+            SmoothingType::Linear => {
+                if self.steps_left > 0 {
+                    self.steps_left -= 1;
+                    self.current = if self.steps_left == 0 { self.target } else { self.current + self.step };
+                }
+            }
+            SmoothingType::Exponential | SmoothingType::MultiStage(_) => {
+                let mut input = self.target;
+                for stage in self.stages.iter_mut() {
+                    *stage += self.coeff * (input - *stage);
+                    input = *stage;
+                }
+                self.current = input;
+            }
+        }
+        self.current
+    }
+
+    pub fn current(&self) -> f32 {
+        self.current
+    }
+
+    pub fn target(&self) -> f32 {
+        self.target
+    }
+
+    pub fn is_smoothing(&self) -> bool {
+        self.current != self.target
+    }
+
+    /// Target and ramp position; the type and time are settings and not saved.
+    pub fn save_state(&self, writer: &mut StateWriter) {
+        writer.put_f32(self.target);
+        writer.put_f32(self.current);
+        writer.put_f32(self.step);
+        writer.put_u64(self.steps_left as u64);
+        writer.put_f32s(&self.stages);
+    }
+
+    pub fn load_state(&mut self, reader: &mut StateReader) -> Result<(), Error> {
+        self.target = reader.f32()?;
+        self.current = reader.f32()?;
+        self.step = reader.f32()?;
+        self.steps_left = reader.u64()? as usize;
+        reader.f32s_into(&mut self.stages)
+    }
+
+    fn update_coeff(&mut self) {
+        // Each stage gets an equal share of the time constant so the overall
+        // response time doesn't grow with the number of stages.
+        let stage_time = self.time_secs / self.stages.len().max(1) as f32;
+        let stage_samples = stage_time * self.sample_rate_hz;
+        self.coeff = if stage_samples <= 0.0 { 1.0 } else { 1.0 - (-1.0 / stage_samples).exp() };
+    }
+}
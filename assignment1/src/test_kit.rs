@@ -0,0 +1,274 @@
+use crate::effect::AudioEffect;
+use crate::state::{StateReader, StateWriter};
+
+// Checks any `AudioEffect` implementation should pass, for this crate's effects and for
+// effects implemented downstream. Each check panics with a description of the failing
+// case, so it can be called straight from a test function. Effects are created through
+// a factory taking the channel count, so every check starts from a fresh instance.
+
+/// Creates a fresh effect with the given number of channels.
+pub type EffectFactory = dyn Fn(usize) -> Box<dyn AudioEffect>;
+
+/// Process `num_frames` of the test signal in blocks of `block_size` (the last one
+/// zero-padded to a full block) and return the output channels.
+pub fn render(effect: &mut dyn AudioEffect, num_frames: usize, block_size: usize) -> Vec<Vec<f32>> {
+    let num_channels = effect.num_channels();
+    let mut rendered = vec![Vec::with_capacity(num_frames); num_channels];
+    let mut input = vec![vec![0.0; block_size]; num_channels];
+    let mut output = vec![vec![0.0; block_size]; num_channels];
+    for start in (0..num_frames).step_by(block_size.max(1)) {
+        let len = (num_frames - start).min(block_size);
+        for (channel, samples) in input.iter_mut().enumerate() {
+            for (n, sample) in samples.iter_mut().enumerate() {
+                *sample = if n < len { test_signal(channel, start + n) } else { 0.0 };
+            }
+        }
+        process(effect, &input, &mut output);
+        for (channel, block) in rendered.iter_mut().zip(&output) {
+            channel.extend_from_slice(&block[..len]);
+        }
+    }
+    rendered
+}
+
+/// Output must not depend on how the input is split into blocks.
+pub fn check_block_size_invariance(name: &str, make_effect: &EffectFactory, num_channels: usize, block_sizes: &[usize]) {
+    const NUM_FRAMES: usize = 4096;
+    let mut reference: Option<(usize, Vec<Vec<f32>>)> = None;
+    for &block_size in block_sizes {
+        let mut effect = make_effect(num_channels);
+        let output = render(effect.as_mut(), NUM_FRAMES, block_size);
+        match &reference {
+            None => reference = Some((block_size, output)),
+            Some((reference_size, expected)) => {
+                let difference = max_difference(expected, &output);
+                assert!(
+                    difference <= 1e-6,
+                    "{}: block size {} differs from block size {} by {}",
+                    name, block_size, reference_size, difference
+                );
+            }
+        }
+    }
+}
+
+/// A fresh effect fed silence must output silence.
+pub fn check_silence_in_silence_out(name: &str, make_effect: &EffectFactory, num_channels: usize) {
+    let mut effect = make_effect(num_channels);
+    let block_size = 256;
+    let input = vec![vec![0.0; block_size]; num_channels];
+    let mut output = vec![vec![1.0; block_size]; num_channels];
+    for _ in 0..4 {
+        process(effect.as_mut(), &input, &mut output);
+        assert!(output.iter().flatten().all(|&x| x == 0.0), "{}: silence in gave non-silent output", name);
+    }
+}
+
+/// After `reset`, an effect must behave exactly like a fresh instance.
+pub fn check_reset_equivalence(name: &str, make_effect: &EffectFactory, num_channels: usize) {
+    const NUM_FRAMES: usize = 4096;
+    let mut fresh = make_effect(num_channels);
+    let block_size = 256;
+    let expected = render(fresh.as_mut(), NUM_FRAMES, block_size);
+    let mut used = make_effect(num_channels);
+    render(used.as_mut(), NUM_FRAMES / 2, block_size);
+    used.reset();
+    let output = render(used.as_mut(), NUM_FRAMES, block_size);
+    let difference = max_difference(&expected, &output);
+    assert!(difference == 0.0, "{}: output after reset differs from a fresh instance by {}", name, difference);
+}
+
+/// Subnormal input must not turn into finite-but-large or non-finite output.
+pub fn check_denormal_input(name: &str, make_effect: &EffectFactory, num_channels: usize) {
+    let mut effect = make_effect(num_channels);
+    let block_size = 256;
+    let subnormal = f32::MIN_POSITIVE / 4.0;
+    let input: Vec<Vec<f32>> = (0..num_channels)
+        .map(|_| (0..block_size).map(|n| if n % 2 == 0 { subnormal } else { -subnormal }).collect())
+        .collect();
+    let mut output = vec![vec![0.0; block_size]; num_channels];
+    for _ in 0..4 {
+        process(effect.as_mut(), &input, &mut output);
+        assert!(
+            output.iter().flatten().all(|x| x.is_finite() && x.abs() < 1e-30),
+            "{}: subnormal input produced {:?}",
+            name,
+            output.iter().flatten().find(|x| !(x.is_finite() && x.abs() < 1e-30))
+        );
+    }
+}
+
+/// NaN and infinite input may produce anything, but `reset` must bring the effect back
+/// to finite output.
+pub fn check_non_finite_recovery(name: &str, make_effect: &EffectFactory, num_channels: usize) {
+    for bad in [f32::NAN, f32::INFINITY, f32::NEG_INFINITY] {
+        let mut effect = make_effect(num_channels);
+        let block_size = 256;
+        let mut input = vec![vec![0.0; block_size]; num_channels];
+        input.iter_mut().for_each(|channel| channel[0] = bad);
+        let mut output = vec![vec![0.0; block_size]; num_channels];
+        process(effect.as_mut(), &input, &mut output);
+        effect.reset();
+        let output = render(effect.as_mut(), 4 * block_size, block_size);
+        assert!(output.iter().flatten().all(|x| x.is_finite()), "{}: still non-finite after {} input and reset", name, bad);
+    }
+}
+
+/// A fresh effect loaded with the saved state of a used one must continue exactly like
+/// the used one.
+pub fn check_state_round_trip(name: &str, make_effect: &EffectFactory, num_channels: usize) {
+    const NUM_FRAMES: usize = 4096;
+    let mut used = make_effect(num_channels);
+    let block_size = 256;
+    render(used.as_mut(), NUM_FRAMES / 2, block_size);
+    let mut writer = StateWriter::new();
+    used.save_state(&mut writer);
+    let bytes = writer.into_bytes();
+    let mut restored = make_effect(num_channels);
+    let mut reader = StateReader::new(&bytes);
+    if let Err(e) = restored.load_state(&mut reader) {
+        panic!("{}: failed to load its own state: {}", name, e);
+    }
+    assert_eq!(reader.remaining(), 0, "{}: state not fully read back", name);
+    let difference = max_difference(&render(used.as_mut(), NUM_FRAMES, block_size), &render(restored.as_mut(), NUM_FRAMES, block_size));
+    assert!(difference == 0.0, "{}: output after loading saved state differs by {}", name, difference);
+}
+
+/// Run every check with defaults: 2 channels, block sizes 1, 64, 256 and 1000, and the
+/// parameter boundaries in `param_values`.
+/// # Example
+/// ```
+/// use ase::{test_kit, AudioEffect, CombFilter, FilterType};
+/// let make_filter = |num_channels| {
+///     let filter = CombFilter::builder(FilterType::FIR, 44100.0).num_channels(num_channels).delay_secs(0.001).build();
+///     Box::new(filter.unwrap()) as Box<dyn AudioEffect>
+/// };
+/// test_kit::check_all("comb filter", &make_filter, &[("gain", &[0.0, 1.0])]);
+/// ```
+pub fn check_all(name: &str, make_effect: &EffectFactory, param_values: &[(&str, &[f32])]) {
+    check_block_size_invariance(name, make_effect, 2, &[1, 64, 256, 1000]);
+    check_silence_in_silence_out(name, make_effect, 2);
+    check_reset_equivalence(name, make_effect, 2);
+    check_denormal_input(name, make_effect, 2);
+    check_non_finite_recovery(name, make_effect, 2);
+    check_state_round_trip(name, make_effect, 2);
+    check_parameter_boundaries(name, make_effect, param_values);
+}
+
+/// Boundary matrix: each combination of channel count (1, 2, 8), block size (0, 1, 2,
+/// 64, 1024) and parameter values from `param_values` (one list per parameter). Values
+/// the effect rejects are skipped, since rejecting them is valid boundary behavior.
+/// Every accepted case must produce finite output, read back the parameters it was given, treat an empty
+/// block as a no-op, and return to silence after `reset`. Returns the number of cases run.
+pub fn check_parameter_boundaries(
+    name: &str,
+    make_effect: &EffectFactory,
+    param_values: &[(&str, &[f32])],
+) -> usize {
+    const CHANNEL_COUNTS: [usize; 3] = [1, 2, 8];
+    const BLOCK_SIZES: [usize; 5] = [0, 1, 2, 64, 1024];
+    // Every combination of one value per parameter
+    let combinations = param_values.iter().fold(vec![Vec::new()], |combinations, (param, values)| {
+        combinations
+            .iter()
+            .flat_map(|combination| {
+                values.iter().map(move |&value| {
+                    let mut combination: Vec<(&str, f32)> = combination.clone();
+                    combination.push((param, value));
+                    combination
+                })
+            })
+            .collect()
+    });
+
+    let mut num_cases = 0;
+    for &num_channels in &CHANNEL_COUNTS {
+        for &block_size in &BLOCK_SIZES {
+            for combination in &combinations {
+                let mut effect = make_effect(num_channels);
+                if combination.iter().any(|&(param, value)| effect.set_param_by_name(param, value).is_err()) {
+                    continue;
+                }
+                let case = format!("{} with {} channels, block size {}, params {:?}", name, num_channels, block_size, combination);
+                for &(param, value) in combination {
+                    let read_back = effect.get_param_by_name(param).expect("Parameter name not readable");
+                    assert!((read_back - value).abs() <= 1e-6 * value.abs().max(1.0), "{}: {} reads back as {}", case, param, read_back);
+                }
+
+                // Impulse followed by full-scale noise-like input, over a few blocks
+                let mut input = vec![vec![0.0_f32; block_size]; num_channels];
+                let mut output = vec![vec![0.0_f32; block_size]; num_channels];
+                for block in 0..3 {
+                    for (channel, samples) in input.iter_mut().enumerate() {
+                        for (n, sample) in samples.iter_mut().enumerate() {
+                            *sample = test_signal(channel, block * block_size + n);
+                        }
+                    }
+                    process(effect.as_mut(), &input, &mut output);
+                    assert!(output.iter().flatten().all(|x| x.is_finite()), "{}: non-finite output", case);
+                }
+
+                // After reset, silence in gives silence out, and empty blocks change nothing
+                effect.reset();
+                effect.process(&vec![&[][..]; num_channels], &mut (0..num_channels).map(|_| &mut [][..]).collect::<Vec<_>>());
+                input.iter_mut().for_each(|x| x.fill(0.0));
+                process(effect.as_mut(), &input, &mut output);
+                assert!(output.iter().flatten().all(|&x| x == 0.0), "{}: reset left state behind", case);
+                num_cases += 1;
+            }
+        }
+    }
+    num_cases
+}
+
+// Deterministic input: a full-scale impulse at the start, then a noise-like sequence
+// in -1..=1 that differs between channels
+fn test_signal(channel: usize, index: usize) -> f32 {
+    if index == 0 {
+        1.0
+    } else {
+        ((index * 7919 + channel * 104729) % 2001) as f32 / 1000.0 - 1.0
+    }
+}
+
+fn process(effect: &mut dyn AudioEffect, input: &[Vec<f32>], output: &mut [Vec<f32>]) {
+    effect.process(&input.iter().map(|x| &x[..]).collect::<Vec<_>>(), &mut output.iter_mut().map(|x| &mut x[..]).collect::<Vec<_>>());
+}
+
+fn max_difference(a: &[Vec<f32>], b: &[Vec<f32>]) -> f32 {
+    a.iter().flatten().zip(b.iter().flatten()).map(|(x, y)| (x - y).abs()).fold(0.0, f32::max)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::comb_filter::{CombFilter, FilterType, MAX_IIR_GAIN};
+    use crate::effect::EffectChain;
+
+    #[test]
+    fn test_effect_boundary_matrix() {
+        const SAMPLE_RATE: f32 = 44100.0;
+        const MAX_DELAY_SECS: f32 = 0.01;
+        // Zero, sub-sample, one-sample and maximum delays; no gain, the largest IIR gain,
+        // unity and above-unity gain (which IIR rejects)
+        let delays = [0.0, 0.5 / SAMPLE_RATE, 1.0 / SAMPLE_RATE, MAX_DELAY_SECS];
+        let gains = [0.0, MAX_IIR_GAIN, 1.0, 1.5];
+        let comb_params: [(&str, &[f32]); 2] = [("delay", &delays), ("gain", &gains)];
+        for filter_type in [FilterType::FIR, FilterType::IIR] {
+            // Without strict mode, so recovery doesn't hide non-finite output the filter itself produces
+            let make_filter = move |num_channels| {
+                let mut filter = CombFilter::new(filter_type, MAX_DELAY_SECS, SAMPLE_RATE, num_channels, 0.5, 1.0 / SAMPLE_RATE).unwrap();
+                filter.set_strict(false);
+                Box::new(filter) as Box<dyn AudioEffect>
+            };
+            check_all(&format!("{:?} comb filter", filter_type), &make_filter, &comb_params);
+        }
+        let make_chain = move |num_channels| {
+            let mut chain = EffectChain::new(num_channels, 1024);
+            chain.push(Box::new(CombFilter::new(FilterType::FIR, MAX_DELAY_SECS, SAMPLE_RATE, num_channels, 0.5, 0.0).unwrap()));
+            chain.push(Box::new(CombFilter::new(FilterType::IIR, MAX_DELAY_SECS, SAMPLE_RATE, num_channels, 0.5, 1.0 / SAMPLE_RATE).unwrap()));
+            Box::new(chain) as Box<dyn AudioEffect>
+        };
+        check_all("effect chain", &make_chain, &[]);
+    }
+}
@@ -0,0 +1,24 @@
+//! WAV fixtures shared by the tests that render through files.
+
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Unique path in the temp directory, so tests running in parallel don't share files.
+pub(crate) fn temp_path(name: &str) -> PathBuf {
+    static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+    std::env::temp_dir().join(format!("ase_test_{}_{}_{}", std::process::id(), NEXT_ID.fetch_add(1, Ordering::Relaxed), name))
+}
+
+/// 32-bit float WAV fixture from interleaved samples.
+pub(crate) fn write_wav(name: &str, num_channels: u16, sample_rate: u32, samples: impl IntoIterator<Item = f32>) -> PathBuf {
+    let path = temp_path(name);
+    let spec = hound::WavSpec { channels: num_channels, sample_rate, bits_per_sample: 32, sample_format: hound::SampleFormat::Float };
+    let mut writer = hound::WavWriter::create(&path, spec).expect("Failed to create test WAV file");
+    samples.into_iter().for_each(|sample| writer.write_sample(sample).unwrap());
+    writer.finalize().unwrap();
+    path
+}
+
+pub(crate) fn read_wav(path: &Path) -> Vec<f32> {
+    hound::WavReader::open(path).unwrap().samples::<f32>().map(Result::unwrap).collect()
+}
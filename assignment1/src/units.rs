@@ -0,0 +1,72 @@
+// Conversion helpers shared by the filters and the binary.
+//
+// Time to sample conversions round to the nearest sample (ties away from zero)
+// rather than truncating, so e.g. 0.01 s at 44100 Hz is always 441 samples even
+// when the float product lands just below the integer.
+
+pub fn secs_to_samples(secs: f32, sample_rate_hz: f32) -> usize {
+    (secs * sample_rate_hz).round().max(0.0) as usize
+}
+
+/// Unrounded sample count, for delays that keep their fractional part.
+pub fn secs_to_frac_samples(secs: f32, sample_rate_hz: f32) -> f32 {
+    (secs * sample_rate_hz).max(0.0)
+}
+
+pub fn samples_to_secs(samples: usize, sample_rate_hz: f32) -> f32 {
+    samples as f32 / sample_rate_hz
+}
+
+pub fn ms_to_samples(ms: f32, sample_rate_hz: f32) -> usize {
+    secs_to_samples(ms / 1000.0, sample_rate_hz)
+}
+
+pub fn samples_to_ms(samples: usize, sample_rate_hz: f32) -> f32 {
+    samples_to_secs(samples, sample_rate_hz) * 1000.0
+}
+
+pub fn db_to_linear(db: f32) -> f32 {
+    10.0_f32.powf(db / 20.0)
+}
+
+/// Returns `f32::NEG_INFINITY` for a gain of zero.
+pub fn linear_to_db(gain: f32) -> f32 {
+    20.0 * gain.abs().log10()
+}
+
+/// MIDI note number to frequency, with A4 (note 69) at 440 Hz.
+pub fn note_to_hz(note: f32) -> f32 {
+    440.0 * 2.0_f32.powf((note - 69.0) / 12.0)
+}
+
+pub fn hz_to_note(hz: f32) -> f32 {
+    69.0 + 12.0 * (hz / 440.0).log2()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unit_conversion_boundaries() {
+        // Products that land just below/above a whole sample must round, not truncate
+        assert_eq!(secs_to_samples(0.01, 44100.0), 441);
+        assert_eq!(secs_to_samples(1.0 / 3.0, 48000.0), 16000);
+        assert_eq!(ms_to_samples(0.0, 44100.0), 0);
+        assert_eq!(ms_to_samples(10.0, 44100.0), 441);
+        // Half a sample rounds away from zero
+        assert_eq!(secs_to_samples(1.5, 1.0), 2);
+        // Negative times clamp to zero instead of wrapping
+        assert_eq!(secs_to_samples(-0.1, 44100.0), 0);
+        assert!((samples_to_ms(441, 44100.0) - 10.0).abs() < 1e-4);
+
+        assert!((db_to_linear(0.0) - 1.0).abs() < 1e-6);
+        assert!((db_to_linear(-6.0206) - 0.5).abs() < 1e-4);
+        assert!((linear_to_db(0.5) + 6.0206).abs() < 1e-3);
+        assert_eq!(linear_to_db(0.0), f32::NEG_INFINITY);
+
+        assert!((note_to_hz(69.0) - 440.0).abs() < 1e-3);
+        assert!((note_to_hz(81.0) - 880.0).abs() < 1e-3);
+        assert!((hz_to_note(261.6256) - 60.0).abs() < 1e-3);
+    }
+}
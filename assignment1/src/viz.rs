@@ -0,0 +1,80 @@
+// Small terminal plots for sanity-checking a render without opening a DAW.
+
+use crate::waveform::Peaks;
+
+const BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// One-line bar plot of `values` squeezed into `width` columns, scaled to the largest value.
+/// Each column shows the maximum of the values it covers.
+pub fn sparkline(values: &[f32], width: usize) -> String {
+    if values.is_empty() || width == 0 {
+        return String::new();
+    }
+    let columns: Vec<f32> = (0..width.min(values.len()))
+        .map(|column| {
+            let start = column * values.len() / width.min(values.len());
+            let end = (column + 1) * values.len() / width.min(values.len());
+            values[start..end].iter().copied().fold(0.0, f32::max)
+        })
+        .collect();
+    let top = columns.iter().copied().fold(0.0, f32::max);
+    columns
+        .iter()
+        .map(|&value| {
+            if top <= 0.0 {
+                return ' ';
+            }
+            let level = (value / top * (BARS.len() - 1) as f32).round() as usize;
+            BARS[level.min(BARS.len() - 1)]
+        })
+        .collect()
+}
+
+/// Waveform drawn with `height` text rows (rounded up to an even number), one column
+/// per entry of `peaks`, with full scale at the top and bottom rows.
+pub fn waveform_rows(peaks: &[Peaks], height: usize) -> Vec<String> {
+    let half = height.div_ceil(2).max(1);
+    let rows = 2 * half;
+    (0..rows)
+        .map(|row| {
+            // Amplitude range covered by this row, from +1 at the top to -1 at the bottom
+            let row_top = 1.0 - row as f32 / half as f32;
+            let row_bottom = row_top - 1.0 / half as f32;
+            peaks
+                .iter()
+                .map(|p| if p.max >= row_bottom && p.min <= row_top { '█' } else { ' ' })
+                .collect()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::waveform::WaveformOverview;
+
+    #[test]
+    fn test_waveform_rows_of_known_peaks() {
+        let peaks = [(-1.0, 1.0), (0.6, 0.9), (-0.3, -0.2), (0.1, 0.2)].map(|(min, max)| Peaks { min, max });
+        // Three rows round up to four, a quarter of full scale each
+        assert_eq!(waveform_rows(&peaks, 3), ["██  ", "█  █", "█ █ ", "█   "], "Waveform plot test failed.");
+    }
+
+    #[test]
+    fn test_waveform_rows_of_overview() {
+        let signal = [0.8, 0.7, -0.1, -0.2, -0.9, -0.6];
+        let overview = WaveformOverview::new(&signal, 2);
+        assert_eq!(waveform_rows(&overview.peaks_for_width(3), 2), ["█  ", " ██"]);
+    }
+
+    #[test]
+    fn test_sparkline() {
+        let ramp: Vec<f32> = (0..8).map(|x| x as f32).collect();
+        assert_eq!(sparkline(&ramp, 8), "▁▂▃▄▅▆▇█");
+        // Each column shows the larger of the two values it covers
+        assert_eq!(sparkline(&ramp, 4), "▂▄▆█");
+        assert_eq!(sparkline(&ramp[..2], 4), "▁█");
+        assert_eq!(sparkline(&[0.0; 3], 3), "   ");
+        assert_eq!(sparkline(&[], 3), "");
+    }
+}
@@ -0,0 +1,144 @@
+use std::path::Path;
+
+use hound::WavReader;
+
+use crate::sample_format;
+
+/// Smallest and largest sample value within one bin of the overview.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Peaks {
+    pub min: f32,
+    pub max: f32,
+}
+
+impl Peaks {
+    fn merge(self, other: Peaks) -> Peaks {
+        Peaks { min: self.min.min(other.min), max: self.max.max(other.max) }
+    }
+}
+
+/// Min/max peak pyramid of one channel for drawing waveforms at any zoom level.
+/// Level 0 holds one `Peaks` per `samples_per_bin` samples; every following level
+/// halves the resolution, down to a single bin for the whole signal.
+pub struct WaveformOverview {
+    samples_per_bin: usize,
+    num_samples: usize,
+    levels: Vec<Vec<Peaks>>,
+}
+
+impl WaveformOverview {
+    pub fn new(samples: &[f32], samples_per_bin: usize) -> Self {
+        let samples_per_bin = samples_per_bin.max(1);
+        let base: Vec<Peaks> = samples
+            .chunks(samples_per_bin)
+            .map(|chunk| {
+                let init = Peaks { min: f32::INFINITY, max: f32::NEG_INFINITY };
+                chunk.iter().fold(init, |peaks, &x| peaks.merge(Peaks { min: x, max: x }))
+            })
+            .collect();
+
+        let mut levels = vec![base];
+        while levels.last().is_some_and(|level| level.len() > 1) {
+            let next = levels
+                .last()
+                .unwrap()
+                .chunks(2)
+                .map(|pair| pair.iter().copied().reduce(Peaks::merge).unwrap())
+                .collect();
+            levels.push(next);
+        }
+        WaveformOverview { samples_per_bin, num_samples: samples.len(), levels }
+    }
+
+    /// One overview per channel of a WAV file (any sample encoding).
+    pub fn from_wav(path: &Path, samples_per_bin: usize) -> Result<Vec<Self>, hound::Error> {
+        let mut reader = WavReader::open(path)?;
+        let channels = reader.spec().channels as usize;
+        let mut channel_samples = vec![Vec::new(); channels];
+        for (i, sample) in sample_format::samples_f32(&mut reader).enumerate() {
+            channel_samples[i % channels].push(sample?);
+        }
+        Ok(channel_samples.iter().map(|samples| Self::new(samples, samples_per_bin)).collect())
+    }
+
+    pub fn num_levels(&self) -> usize {
+        self.levels.len()
+    }
+
+    /// Bins at `level`, each covering `samples_per_bin << level` samples.
+    pub fn level(&self, level: usize) -> &[Peaks] {
+        &self.levels[level]
+    }
+
+    pub fn num_samples(&self) -> usize {
+        self.num_samples
+    }
+
+    pub fn samples_per_bin(&self) -> usize {
+        self.samples_per_bin
+    }
+
+    /// Peaks for drawing the whole signal `width` columns wide. Uses the coarsest level
+    /// that still has at least `width` bins and merges bins into columns from there.
+    pub fn peaks_for_width(&self, width: usize) -> Vec<Peaks> {
+        if width == 0 || self.num_samples == 0 {
+            return Vec::new();
+        }
+        let level = self
+            .levels
+            .iter()
+            .rev()
+            .find(|level| level.len() >= width)
+            .unwrap_or(&self.levels[0]);
+        (0..width)
+            .filter_map(|column| {
+                let start = column * level.len() / width;
+                let end = ((column + 1) * level.len() / width).max(start + 1).min(level.len());
+                level[start..end].iter().copied().reduce(Peaks::merge)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_wav::write_wav;
+
+    const SIGNAL: [f32; 7] = [0.1, -0.2, 0.3, 0.5, -0.4, 0.0, 0.9];
+
+    #[test]
+    fn test_overview_buckets_partial_last_bin() {
+        // Seven samples in bins of three leave a last bin of one sample
+        let overview = WaveformOverview::new(&SIGNAL, 3);
+        assert_eq!(overview.num_samples(), 7);
+        assert_eq!(overview.samples_per_bin(), 3);
+        assert_eq!(overview.num_levels(), 3);
+        let peaks = |pairs: &[(f32, f32)]| pairs.iter().map(|&(min, max)| Peaks { min, max }).collect::<Vec<_>>();
+        assert_eq!(overview.level(0), peaks(&[(-0.2, 0.3), (-0.4, 0.5), (0.9, 0.9)]), "Waveform test failed: wrong base level.");
+        assert_eq!(overview.level(1), peaks(&[(-0.4, 0.5), (0.9, 0.9)]), "Waveform test failed: wrong first level.");
+        assert_eq!(overview.level(2), peaks(&[(-0.4, 0.9)]), "Waveform test failed: wrong top level.");
+    }
+
+    #[test]
+    fn test_peaks_for_width() {
+        let overview = WaveformOverview::new(&SIGNAL, 3);
+        assert_eq!(overview.peaks_for_width(1), overview.level(2));
+        assert_eq!(overview.peaks_for_width(2), overview.level(1));
+        assert_eq!(overview.peaks_for_width(3), overview.level(0));
+        // Wider than the base level repeats bins rather than inventing peaks
+        assert_eq!(overview.peaks_for_width(6).len(), 6);
+        assert!(overview.peaks_for_width(6).iter().all(|p| overview.level(0).contains(p)));
+        assert!(overview.peaks_for_width(0).is_empty());
+        assert!(WaveformOverview::new(&[], 3).peaks_for_width(4).is_empty());
+    }
+
+    #[test]
+    fn test_overview_from_wav_splits_channels() {
+        let path = write_wav("overview.wav", 2, 44100, SIGNAL.iter().flat_map(|&x| [x, -x]));
+        let overviews = WaveformOverview::from_wav(&path, 3).unwrap();
+        assert_eq!(overviews.len(), 2);
+        assert_eq!(overviews[0].level(0), WaveformOverview::new(&SIGNAL, 3).level(0));
+        assert_eq!(overviews[1].level(2), [Peaks { min: -0.9, max: 0.4 }]);
+    }
+}
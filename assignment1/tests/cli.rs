@@ -0,0 +1,85 @@
+//! Runs the `ase` executable the way scripts do, checking exit codes and diagnostics.
+
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("ase_cli_test_{}_{}", std::process::id(), name))
+}
+
+// One second of a 0.5 impulse every 100 ms at 44.1 kHz
+fn write_input(name: &str) -> PathBuf {
+    let path = temp_path(name);
+    let spec = hound::WavSpec { channels: 1, sample_rate: 44100, bits_per_sample: 32, sample_format: hound::SampleFormat::Float };
+    let mut writer = hound::WavWriter::create(&path, spec).expect("Failed to create test WAV file");
+    (0..44100).for_each(|n| writer.write_sample(if n % 4410 == 0 { 0.5_f32 } else { 0.0 }).unwrap());
+    writer.finalize().unwrap();
+    path
+}
+
+fn run_json(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_ase")).args(args).args(["--error-format", "json"]).output().expect("Failed to run ase")
+}
+
+#[test]
+fn test_long_delay_render_succeeds_or_reports_one_json_error() {
+    let input_path = write_input("long_delay_in.wav");
+    let output_path = temp_path("long_delay_out.wav");
+    let [input, output] = [&input_path, &output_path].map(|path| path.to_str().unwrap().to_string());
+
+    // 100 ms of delay is 4410 samples, longer than the 1024-frame render blocks
+    let result = run_json(&[&input, &output, "FIR,1.0,44100,0.5,0.1"]);
+    let stderr = String::from_utf8_lossy(&result.stderr);
+    assert!(result.status.success(), "Long delay render failed with {:?}: {}", result.status.code(), stderr);
+    assert!(stderr.is_empty(), "Long delay render printed diagnostics: {}", stderr);
+    assert_eq!(hound::WavReader::open(&output_path).unwrap().len(), 44100);
+
+    // A delay beyond the maximum fails with exactly one JSON error line, not a panic
+    let result = run_json(&[&input, &output, "FIR,0.05,44100,0.5,0.1"]);
+    let stderr = String::from_utf8_lossy(&result.stderr);
+    assert_eq!(result.status.code(), Some(1), "Invalid delay exited with {:?}: {}", result.status.code(), stderr);
+    let lines: Vec<&str> = stderr.lines().collect();
+    assert_eq!(lines.len(), 1, "Expected one diagnostic line, got: {}", stderr);
+    assert!(lines[0].starts_with(r#"{"severity":"error","#) && lines[0].ends_with('}'), "Not a JSON error: {}", lines[0]);
+}
+
+#[test]
+fn test_permissive_render_warns_about_clamped_params() {
+    let input_path = write_input("permissive_in.wav");
+    let output_path = temp_path("permissive_out.wav");
+    let [input, output] = [&input_path, &output_path].map(|path| path.to_str().unwrap().to_string());
+    let args = [input.as_str(), &output, "IIR,0.1,44100,0.5,0.01", "--automate", "0:gain:0=0.5,1=2.5"];
+
+    let result = run_json(&args);
+    assert_eq!(result.status.code(), Some(1), "Out-of-range automation didn't fail: {}", String::from_utf8_lossy(&result.stderr));
+
+    let result = run_json(&[&args[..], &["--permissive"]].concat());
+    let stderr = String::from_utf8_lossy(&result.stderr);
+    assert!(result.status.success(), "Permissive render failed: {}", stderr);
+    let warning = stderr.lines().find(|line| line.contains(r#""code":"clamped_params""#)).expect("No clamped_params warning");
+    assert!(warning.starts_with(r#"{"severity":"warning","#) && warning.contains("Filter 0: "), "Unexpected warning: {}", warning);
+}
+
+#[test]
+fn test_verify_hash_accepts_match_and_rejects_mismatch() {
+    let input_path = write_input("verify_hash_in.wav");
+    let output_path = temp_path("verify_hash_out.wav");
+    let [input, output] = [&input_path, &output_path].map(|path| path.to_str().unwrap().to_string());
+    let args = [input.as_str(), &output, "IIR,0.1,44100,0.5,0.01"];
+
+    let result = run_json(&[&args[..], &["--emit-hash"]].concat());
+    assert!(result.status.success(), "Render failed: {}", String::from_utf8_lossy(&result.stderr));
+    let stdout = String::from_utf8_lossy(&result.stdout);
+    let hash = stdout.lines().find_map(|line| line.strip_prefix("Output hash: ")).expect("No output hash").to_string();
+
+    let result = run_json(&[&args[..], &["--verify-hash", &hash]].concat());
+    assert!(result.status.success(), "Matching hash failed: {}", String::from_utf8_lossy(&result.stderr));
+
+    // Flip the last digit so the hash can't match
+    let last = if hash.ends_with('0') { "1" } else { "0" };
+    let wrong = format!("{}{}", &hash[..hash.len() - 1], last);
+    let result = run_json(&[&args[..], &["--verify-hash", &wrong]].concat());
+    let stderr = String::from_utf8_lossy(&result.stderr);
+    assert_eq!(result.status.code(), Some(2), "Mismatched hash exited with {:?}: {}", result.status.code(), stderr);
+    assert!(stderr.lines().any(|line| line.contains(r#""code":"hash_mismatch""#)), "No hash_mismatch error: {}", stderr);
+}